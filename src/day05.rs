@@ -1,6 +1,5 @@
-use std::collections::{HashMap, HashSet};
-
 use failure::{err_msg, Error};
+use itertools::Itertools;
 
 use nom::{
     bytes::complete::tag,
@@ -10,11 +9,11 @@ use nom::{
     sequence::{separated_pair, terminated},
 };
 
-use crate::parsers::unsigned;
+use crate::{parsers::unsigned, partial_order::PartialOrder};
 
-fn parse_input(input: &str) -> Result<(Rules, PageNumbers), Error> {
+fn parse_input(input: &str) -> Result<(PartialOrder<u32>, PageNumbers), Error> {
     let rule = separated_pair(unsigned, tag("|"), unsigned);
-    let rules = map(many0(terminated(rule, newline)), Rules::new);
+    let rules = map(many0(terminated(rule, newline)), PartialOrder::new);
 
     let page_numbers = map(separated_list0(tag(","), unsigned), |v| {
         v.into_boxed_slice()
@@ -28,107 +27,52 @@ fn parse_input(input: &str) -> Result<(Rules, PageNumbers), Error> {
         .map_err(|err| err_msg(format!("Failed to parse input: {}", err)))
 }
 
-fn follow(start: u32, values: &[u32], links: &HashMap<u32, HashSet<u32>>) -> Vec<u32> {
-    let mut chained = HashSet::new();
-
-    let mut to_check = vec![start];
-
-    while let Some(check) = to_check.pop() {
-        if let Some(candidates) = links.get(&check) {
-            for &val in values {
-                if candidates.contains(&val) && chained.insert(val) {
-                    to_check.push(val);
-                }
-            }
-        }
-    }
-
-    chained.into_iter().collect()
-}
-
-#[derive(Clone)]
-pub struct Rules {
-    before: HashMap<u32, HashSet<u32>>,
-    after: HashMap<u32, HashSet<u32>>,
-}
-
 type PageNumbers = Box<[Box<[u32]>]>;
 
-impl Rules {
-    fn new(rules: Vec<(u32, u32)>) -> Self {
-        let mut after: HashMap<u32, HashSet<u32>> = HashMap::new();
-        let mut before: HashMap<u32, HashSet<u32>> = HashMap::new();
-
-        for (first, second) in rules {
-            after.entry(first).or_default().insert(second);
-            before.entry(second).or_default().insert(first);
-        }
-
-        Rules { before, after }
-    }
-
-    fn in_sequence(&self, page_numbers: &[u32]) -> bool {
-        let mut disallowed: HashSet<u32> = HashSet::new();
-
-        for page in page_numbers {
-            if disallowed.contains(page) {
-                return false;
-            }
-
-            if let Some(before) = self.before.get(page) {
-                disallowed.extend(before);
-            }
-        }
-
-        true
-    }
-
-    fn reorder(&self, page_numbers: &[u32]) -> Box<[u32]> {
-        if let Some(&first) = page_numbers.first() {
-            let before = follow(first, page_numbers, &self.before);
-            let after = follow(first, page_numbers, &self.after);
-
-            let before = self.reorder(&before);
-            let after = self.reorder(&after);
-
-            assert!(before.len() + after.len() + 1 == page_numbers.len());
-
-            let mut new_page_numbers = vec![];
-            new_page_numbers.extend(before);
-            new_page_numbers.push(first);
-            new_page_numbers.extend(after);
-            new_page_numbers.into_boxed_slice()
-        } else {
-            vec![].into_boxed_slice()
-        }
-    }
-}
-
 fn find_mid_number(page_numbers: &[u32]) -> u32 {
     page_numbers[page_numbers.len() / 2]
 }
 
-fn find_ordered_mid_numbers(rules: &Rules, sequences: &[Box<[u32]>]) -> u32 {
+fn find_ordered_mid_numbers(rules: &PartialOrder<u32>, sequences: &[Box<[u32]>]) -> u32 {
     sequences
         .iter()
-        .filter(|page_numbers| rules.in_sequence(page_numbers))
+        .filter(|page_numbers| rules.is_ordered(page_numbers))
         .map(|page_numbers| find_mid_number(page_numbers))
         .sum()
 }
 
-fn find_unordered_mid_numbers(rules: &Rules, sequences: &[Box<[u32]>]) -> u32 {
+fn find_unordered_mid_numbers(
+    rules: &PartialOrder<u32>,
+    sequences: &[Box<[u32]>],
+) -> Result<u32, Error> {
     sequences
         .iter()
-        .filter(|page_numbers| !rules.in_sequence(page_numbers))
-        .map(|page_numbers| rules.reorder(page_numbers))
-        .map(|page_numbers| find_mid_number(&page_numbers))
+        .filter(|page_numbers| !rules.is_ordered(page_numbers))
+        .map(|page_numbers| {
+            rules
+                .sort(page_numbers)
+                .map(|page_numbers| find_mid_number(&page_numbers))
+                .map_err(|err| {
+                    let broken = rules
+                        .violations(page_numbers)
+                        .iter()
+                        .map(|(first, second)| format!("{}|{}", first, second))
+                        .join(", ");
+                    err_msg(format!(
+                        "Can't reorder {} which breaks {}: {}",
+                        page_numbers.iter().join(","),
+                        broken,
+                        err
+                    ))
+                })
+        })
         .sum()
 }
 
 pub struct Solver {}
 
 impl super::Solver for Solver {
-    type Problem = (Rules, Box<[Box<[u32]>]>);
+    type Problem = (PartialOrder<u32>, PageNumbers);
 
     fn parse_input(data: String) -> Result<Self::Problem, Error> {
         parse_input(&data)
@@ -136,8 +80,71 @@ impl super::Solver for Solver {
 
     fn solve((rules, page_numbers): Self::Problem) -> (Option<String>, Option<String>) {
         let part1 = find_ordered_mid_numbers(&rules, &page_numbers);
-        let part2 = find_unordered_mid_numbers(&rules, &page_numbers);
+        let part2 = match find_unordered_mid_numbers(&rules, &page_numbers) {
+            Ok(part2) => Some(part2.to_string()),
+            Err(err) => {
+                println!("{}", err);
+                None
+            }
+        };
+
+        (Some(part1.to_string()), part2)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{find_ordered_mid_numbers, find_unordered_mid_numbers, parse_input};
+
+    const EXAMPLE: &str = "\
+47|53
+97|13
+97|61
+97|47
+75|29
+61|13
+75|53
+29|13
+97|29
+53|29
+61|53
+97|53
+61|29
+47|13
+75|47
+97|75
+47|61
+75|61
+47|29
+75|13
+53|13
+
+75,47,61,53,29
+97,61,53,29,13
+75,29,13
+75,97,47,61,53
+61,13,29
+97,13,75,29,47
+";
+
+    #[test]
+    fn test_mid_numbers() {
+        let (rules, page_numbers) = parse_input(EXAMPLE).unwrap();
+        assert_eq!(find_ordered_mid_numbers(&rules, &page_numbers), 143);
+        assert_eq!(
+            find_unordered_mid_numbers(&rules, &page_numbers).unwrap(),
+            123
+        );
+        assert_eq!(rules.violations(&[61, 13, 29]), vec![(29, 13)]);
+    }
 
-        (Some(part1.to_string()), Some(part2.to_string()))
+    #[test]
+    fn test_cycle() {
+        let (rules, page_numbers) = parse_input("1|2\n2|3\n3|1\n\n3,2,1\n").unwrap();
+        let err = find_unordered_mid_numbers(&rules, &page_numbers).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Can't reorder 3,2,1 which breaks 1|2, 2|3: Rules form a cycle: 3 -> 1 -> 2 -> 3"
+        );
     }
 }
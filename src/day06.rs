@@ -1,8 +1,14 @@
-use std::collections::HashSet;
+use std::{collections::HashSet, path::Path};
 
+use ansi_term::Colour;
 use failure::Error;
+use itertools::{iproduct, Itertools};
 
-use crate::common::{Direction, Position};
+use crate::{
+    common::{Direction, Position},
+    display::{Frame, Player},
+    export, parallel,
+};
 
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
 pub struct Guard {
@@ -31,16 +37,6 @@ pub struct Grid {
 }
 
 impl Grid {
-    fn with_obstacle_at(&self, position: Position) -> Self {
-        let mut obstacles = self.obstacles.clone();
-        obstacles.insert(position);
-        Grid {
-            obstacles,
-            width: self.width,
-            height: self.height,
-        }
-    }
-
     fn is_occupied(&self, position: Position) -> bool {
         self.obstacles.contains(&position)
     }
@@ -48,6 +44,100 @@ impl Grid {
     fn inside(&self, position: Position) -> bool {
         (0..self.width).contains(&position.x) && (0..self.height).contains(&position.y)
     }
+
+    fn positions(&self) -> impl Iterator<Item = Position> {
+        iproduct!(0..self.height, 0..self.width).map(|(y, x)| Position { x, y })
+    }
+}
+
+fn slot(direction: Direction) -> usize {
+    match direction {
+        Direction::North => 0,
+        Direction::East => 1,
+        Direction::South => 2,
+        Direction::West => 3,
+        _ => unreachable!("Guard can only face a cardinal direction"),
+    }
+}
+
+// For each position and direction, where the guard stops walking before the next
+// obstacle, or None if it walks off the grid.
+struct JumpTable {
+    width: i64,
+    stops: Vec<[Option<Position>; 4]>,
+}
+
+impl JumpTable {
+    fn new(grid: &Grid) -> Self {
+        let mut table = JumpTable {
+            width: grid.width,
+            stops: vec![[None; 4]; (grid.width * grid.height) as usize],
+        };
+
+        for direction in Direction::cardinal() {
+            let offset = direction.offset();
+
+            // Fill in each position after the one in front of it.
+            let mut positions: Vec<_> = grid.positions().collect();
+            positions.sort_by_key(|position| -(position.x * offset.x + position.y * offset.y));
+
+            for position in positions {
+                let next = position.step(direction);
+                let stop = if grid.is_occupied(next) {
+                    Some(position)
+                } else if grid.inside(next) {
+                    table.stop(next, direction)
+                } else {
+                    None
+                };
+                let index = table.index(position);
+                table.stops[index][slot(direction)] = stop;
+            }
+        }
+
+        table
+    }
+
+    fn index(&self, position: Position) -> usize {
+        (position.y * self.width + position.x) as usize
+    }
+
+    fn stop(&self, position: Position, direction: Direction) -> Option<Position> {
+        self.stops[self.index(position)][slot(direction)]
+    }
+
+    // Where the guard stops if there's an extra obstacle, which might be in its way.
+    fn stop_with_obstacle(&self, guard: Guard, obstacle: Position) -> Option<Position> {
+        let stop = self.stop(guard.position, guard.direction);
+
+        let offset = guard.direction.offset();
+        let diff = obstacle - guard.position;
+        let distance = diff.x * offset.x + diff.y * offset.y;
+        if distance > 0 && diff == offset * distance {
+            let reach = stop.map_or(i64::MAX, |stop| (stop - guard.position).length());
+            if distance <= reach {
+                return Some(obstacle - offset);
+            }
+        }
+
+        stop
+    }
+
+    fn loops_with_obstacle(&self, mut guard: Guard, obstacle: Position) -> bool {
+        let mut turns = HashSet::new();
+
+        while let Some(position) = self.stop_with_obstacle(guard, obstacle) {
+            guard = Guard {
+                position,
+                direction: guard.direction.turn_right(),
+            };
+            if !turns.insert(guard) {
+                return true;
+            }
+        }
+
+        false
+    }
 }
 
 fn find_positions<'a>(rows: &'a [&'a str], c: char) -> impl Iterator<Item = Position> + 'a {
@@ -73,32 +163,58 @@ fn find_visited_positions(grid: &Grid, guard: Guard) -> HashSet<Position> {
         .collect()
 }
 
-fn path_loops(grid: &Grid, mut guard: Guard) -> bool {
-    let mut visited = HashSet::new();
-    visited.insert(guard);
+// Each position on the guard's route where an obstacle could go, along with where the
+// guard is just before it would first reach it.
+fn find_candidate_obstacles(grid: &Grid, guard: Guard) -> Vec<(Position, Guard)> {
+    let mut seen = HashSet::new();
+    seen.insert(guard.position);
 
-    while guard.do_move(grid) {
-        if !visited.insert(guard) {
-            return true;
-        }
-    }
+    find_guard_locations(grid, guard)
+        .tuple_windows()
+        .filter(|&(_, next)| seen.insert(next.position))
+        .map(|(previous, next)| (next.position, previous))
+        .collect()
+}
+
+fn count_loop_locations(grid: &Grid, guard_start: Guard) -> usize {
+    let table = JumpTable::new(grid);
+    let candidates = find_candidate_obstacles(grid, guard_start);
+
+    parallel::count(&candidates, |&(obstacle, guard)| {
+        table.loops_with_obstacle(guard, obstacle)
+    })
+}
+
+fn walk_frames(grid: &Grid, guard: Guard) -> impl Iterator<Item = Frame> + '_ {
+    let size = Position {
+        x: grid.width,
+        y: grid.height,
+    };
+
+    find_guard_locations(grid, guard).scan(HashSet::new(), move |visited, guard| {
+        visited.insert(guard.position);
+
+        let mut frame = Frame::new(size).caption(format!("Visited {}", visited.len()));
+        frame.draw(&grid.obstacles, '#', Colour::Red.dimmed());
+        frame.draw(visited.iter(), 'X', Colour::Green.normal());
+        frame.set(
+            guard.position,
+            guard.direction.as_char(),
+            Colour::Blue.bold(),
+        );
 
-    false
+        Some(frame)
+    })
+}
+
+pub fn visualize_input(data: String) -> Result<(), Error> {
+    let (grid, guard) = <Solver as super::Solver>::parse_input(data)?;
+    Player::new(20).play(walk_frames(&grid, guard))
 }
 
-fn count_loop_locations(
-    grid: &Grid,
-    visited_positions: &HashSet<Position>,
-    guard_start: Guard,
-) -> usize {
-    visited_positions
-        .iter()
-        .filter(|&&position| position != guard_start.position)
-        .filter(|&&position| {
-            let new_grid = grid.with_obstacle_at(position);
-            path_loops(&new_grid, guard_start)
-        })
-        .count()
+pub fn export_input(data: String, path: &Path, max_frames: usize) -> Result<(), Error> {
+    let (grid, guard) = <Solver as super::Solver>::parse_input(data)?;
+    export::save(path, walk_frames(&grid, guard), max_frames)
 }
 
 pub struct Solver {}
@@ -132,10 +248,117 @@ impl super::Solver for Solver {
     }
 
     fn solve((grid, guard): Self::Problem) -> (Option<String>, Option<String>) {
-        let visited_positions = find_visited_positions(&grid, guard);
-        let part1 = visited_positions.len();
-        let part2 = count_loop_locations(&grid, &visited_positions, guard);
+        let part1 = find_visited_positions(&grid, guard).len();
+        let part2 = count_loop_locations(&grid, guard);
 
         (Some(part1.to_string()), Some(part2.to_string()))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::{collections::HashSet, time::Instant};
+
+    use super::{count_loop_locations, find_visited_positions, Grid, Guard, Solver};
+    use crate::{
+        common::{Direction, Position},
+        Solver as _,
+    };
+
+    const EXAMPLE: &str = "\
+....#.....
+.........#
+..........
+..#.......
+.......#..
+..........
+.#..^.....
+........#.
+#.........
+......#...
+";
+
+    fn with_obstacle_at(grid: &Grid, position: Position) -> Grid {
+        let mut obstacles = grid.obstacles.clone();
+        obstacles.insert(position);
+        Grid {
+            obstacles,
+            width: grid.width,
+            height: grid.height,
+        }
+    }
+
+    fn path_loops(grid: &Grid, mut guard: Guard) -> bool {
+        let mut visited = HashSet::new();
+        visited.insert(guard);
+
+        while guard.do_move(grid) {
+            if !visited.insert(guard) {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    fn count_loop_locations_cloning(grid: &Grid, guard_start: Guard) -> usize {
+        find_visited_positions(grid, guard_start)
+            .iter()
+            .filter(|&&position| position != guard_start.position)
+            .filter(|&&position| path_loops(&with_obstacle_at(grid, position), guard_start))
+            .count()
+    }
+
+    #[test]
+    fn test_loop_locations() {
+        let (grid, guard) = Solver::parse_input(EXAMPLE.to_string()).unwrap();
+        assert_eq!(find_visited_positions(&grid, guard).len(), 41);
+        assert_eq!(count_loop_locations(&grid, guard), 6);
+        assert_eq!(count_loop_locations_cloning(&grid, guard), 6);
+    }
+
+    #[test]
+    #[ignore]
+    fn bench_jump_tables() {
+        // A spiral, so that the guard's route covers most of the grid.
+        let size = 130;
+        let obstacles = (0..)
+            .map(|ring| (1 + 2 * ring, size - 2 - 2 * ring))
+            .take_while(|&(low, high)| low + 2 < high)
+            .flat_map(|(low, high)| {
+                [
+                    (low, low - 1),
+                    (high + 1, low),
+                    (high, high + 1),
+                    (low + 1, high),
+                ]
+            })
+            .map(|(x, y)| Position { x, y })
+            .collect();
+        let grid = Grid {
+            obstacles,
+            width: size,
+            height: size,
+        };
+        let guard = Guard {
+            position: Position { x: 1, y: size - 2 },
+            direction: Direction::North,
+        };
+
+        let start = Instant::now();
+        let cloning = count_loop_locations_cloning(&grid, guard);
+        let cloning_time = start.elapsed();
+
+        let start = Instant::now();
+        let jumping = count_loop_locations(&grid, guard);
+        let jumping_time = start.elapsed();
+
+        assert_eq!(cloning, jumping);
+        println!(
+            "Cloning grid: {:?}, jump tables: {:?} ({:.2}x)",
+            cloning_time,
+            jumping_time,
+            cloning_time.as_secs_f64() / jumping_time.as_secs_f64()
+        );
+    }
+}
@@ -1,4 +1,6 @@
-use crate::parsers::unsigned;
+use std::fmt::{self, Display};
+
+use crate::{parallel, parsers::unsigned};
 
 use nom::{
     bytes::complete::tag,
@@ -10,60 +12,204 @@ use nom::{
 
 use failure::{err_msg, Error};
 
-#[derive(Debug, Clone, Copy)]
-enum Operator {
-    Add,
-    Mul,
-    Concat,
+// The left hand values that give a result, such as any value at all for 0 = x * 0.
+enum Left {
+    Exactly(u64),
+    Any,
+}
+
+// An operator that can be un-applied: given the result and the right hand value, find
+// the left hand value, if there is one.
+trait Operator: Sync {
+    fn symbol(&self) -> &str;
+    fn apply(&self, left: u64, right: u64) -> Option<u64>;
+    fn unapply(&self, result: u64, right: u64) -> Option<Left>;
+}
+
+struct Add;
+
+impl Operator for Add {
+    fn symbol(&self) -> &str {
+        "+"
+    }
+
+    fn apply(&self, left: u64, right: u64) -> Option<u64> {
+        left.checked_add(right)
+    }
+
+    fn unapply(&self, result: u64, right: u64) -> Option<Left> {
+        result.checked_sub(right).map(Left::Exactly)
+    }
 }
 
-impl Operator {
-    fn basic() -> Box<[Operator]> {
-        Box::new([Operator::Add, Operator::Mul])
+struct Mul;
+
+impl Operator for Mul {
+    fn symbol(&self) -> &str {
+        "*"
     }
 
-    fn extended() -> Box<[Operator]> {
-        Box::new([Operator::Add, Operator::Mul, Operator::Concat])
+    fn apply(&self, left: u64, right: u64) -> Option<u64> {
+        left.checked_mul(right)
     }
 
-    fn apply(self, left: u64, right: u64) -> u64 {
-        match self {
-            Operator::Add => left + right,
-            Operator::Mul => left * right,
-            Operator::Concat => left * 10u64.pow(right.ilog10() + 1) + right,
+    fn unapply(&self, result: u64, right: u64) -> Option<Left> {
+        if right == 0 {
+            (result == 0).then_some(Left::Any)
+        } else if result.is_multiple_of(right) {
+            Some(Left::Exactly(result / right))
+        } else {
+            None
         }
     }
 }
 
+struct Concat;
+
+impl Concat {
+    fn shift(right: u64) -> u64 {
+        10u64.pow(right.checked_ilog10().unwrap_or(0) + 1)
+    }
+}
+
+impl Operator for Concat {
+    fn symbol(&self) -> &str {
+        "||"
+    }
+
+    fn apply(&self, left: u64, right: u64) -> Option<u64> {
+        left.checked_mul(Self::shift(right))?.checked_add(right)
+    }
+
+    fn unapply(&self, result: u64, right: u64) -> Option<Left> {
+        let shift = Self::shift(right);
+        if result >= right && result % shift == right {
+            Some(Left::Exactly(result / shift))
+        } else {
+            None
+        }
+    }
+}
+
+const BASIC: &[&dyn Operator] = &[&Add, &Mul];
+const EXTENDED: &[&dyn Operator] = &[&Add, &Mul, &Concat];
+
+// The operators that make an equation true, evaluated left to right.
+pub struct Witness<'a> {
+    values: &'a [u64],
+    operators: Vec<&'a dyn Operator>,
+}
+
+impl Witness<'_> {
+    fn evaluate(&self) -> Option<u64> {
+        self.operators
+            .iter()
+            .zip(&self.values[1..])
+            .try_fold(self.values[0], |total, (op, &value)| op.apply(total, value))
+    }
+}
+
+impl Display for Witness<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.values[0])?;
+        for (op, value) in self.operators.iter().zip(&self.values[1..]) {
+            write!(f, " {} {}", op.symbol(), value)?;
+        }
+        Ok(())
+    }
+}
+
 pub struct Equation {
     result: u64,
     values: Box<[u64]>,
 }
 
 impl Equation {
-    fn has_solution(&self, operators: &[Operator]) -> bool {
-        let mut stack = vec![(self.values[0], &self.values[1..])];
+    // Works back from the result, un-applying operators from the last value, which rules
+    // out most combinations early.
+    fn solve<'a>(&'a self, operators: &[&'a dyn Operator]) -> Option<Witness<'a>> {
+        fn unwind<'a>(
+            target: u64,
+            values: &[u64],
+            operators: &[&'a dyn Operator],
+            chosen: &mut Vec<&'a dyn Operator>,
+        ) -> bool {
+            let (&last, rest) = values.split_last().unwrap();
+            if rest.is_empty() {
+                return target == last;
+            }
 
-        while let Some((total, remaining)) = stack.pop() {
-            if !remaining.is_empty() {
-                for op in operators {
-                    stack.push((op.apply(total, remaining[0]), &remaining[1..]));
+            for &op in operators {
+                let found = match op.unapply(target, last) {
+                    Some(Left::Exactly(left)) => {
+                        chosen.push(op);
+                        unwind(left, rest, operators, chosen)
+                    }
+                    Some(Left::Any) => {
+                        chosen.push(op);
+                        let mut forwards = vec![];
+                        let found = evaluate(rest[0], &rest[1..], operators, &mut forwards);
+                        chosen.extend(forwards.into_iter().rev());
+                        found
+                    }
+                    None => continue,
+                };
+                if found {
+                    return true;
                 }
-            } else if total == self.result {
+                chosen.pop();
+            }
+
+            false
+        }
+
+        // Whether the values evaluate to anything at all without overflowing.
+        fn evaluate<'a>(
+            total: u64,
+            values: &[u64],
+            operators: &[&'a dyn Operator],
+            chosen: &mut Vec<&'a dyn Operator>,
+        ) -> bool {
+            let Some((&next, rest)) = values.split_first() else {
                 return true;
+            };
+
+            for &op in operators {
+                if let Some(total) = op.apply(total, next) {
+                    chosen.push(op);
+                    if evaluate(total, rest, operators, chosen) {
+                        return true;
+                    }
+                    chosen.pop();
+                }
             }
+
+            false
         }
 
-        false
+        let mut chosen = vec![];
+        if unwind(self.result, &self.values, operators, &mut chosen) {
+            chosen.reverse();
+            Some(Witness {
+                values: &self.values,
+                operators: chosen,
+            })
+        } else {
+            None
+        }
     }
 }
 
-fn find_total_valid_sum(equations: &[Equation], operators: &[Operator]) -> u64 {
-    equations
-        .iter()
-        .filter(|eq| eq.has_solution(operators))
-        .map(|eq| eq.result)
-        .sum()
+fn find_total_valid_sum(equations: &[Equation], operators: &[&dyn Operator]) -> u64 {
+    parallel::map(equations, |eq| match eq.solve(operators) {
+        Some(witness) => {
+            debug_assert_eq!(witness.evaluate(), Some(eq.result));
+            eq.result
+        }
+        None => 0,
+    })
+    .into_iter()
+    .sum()
 }
 
 pub struct Solver {}
@@ -89,8 +235,70 @@ impl super::Solver for Solver {
     }
 
     fn solve(equations: Self::Problem) -> (Option<String>, Option<String>) {
-        let part1 = find_total_valid_sum(&equations, &Operator::basic());
-        let part2 = find_total_valid_sum(&equations, &Operator::extended());
+        let part1 = find_total_valid_sum(&equations, BASIC);
+        let part2 = find_total_valid_sum(&equations, EXTENDED);
         (Some(part1.to_string()), Some(part2.to_string()))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{find_total_valid_sum, Left, Operator, Solver, BASIC, EXTENDED};
+    use crate::Solver as _;
+
+    const EXAMPLE: &str = "\
+190: 10 19
+3267: 81 40 27
+83: 17 5
+156: 15 6
+7290: 6 8 6 15
+161011: 16 10 13
+192: 17 8 14
+21037: 9 7 18 13
+292: 11 6 16 20
+";
+
+    struct Sub;
+
+    impl Operator for Sub {
+        fn symbol(&self) -> &str {
+            "-"
+        }
+
+        fn apply(&self, left: u64, right: u64) -> Option<u64> {
+            left.checked_sub(right)
+        }
+
+        fn unapply(&self, result: u64, right: u64) -> Option<Left> {
+            result.checked_add(right).map(Left::Exactly)
+        }
+    }
+
+    #[test]
+    fn test_witnesses() {
+        let equations = Solver::parse_input(EXAMPLE.to_string()).unwrap();
+        assert_eq!(find_total_valid_sum(&equations, BASIC), 3749);
+        assert_eq!(find_total_valid_sum(&equations, EXTENDED), 11387);
+
+        let witness = equations[1].solve(BASIC).unwrap();
+        assert_eq!(witness.evaluate(), Some(3267));
+        assert!(["81 + 40 * 27", "81 * 40 + 27"].contains(&witness.to_string().as_str()));
+
+        let witness = equations[4].solve(EXTENDED).unwrap();
+        assert_eq!(witness.to_string(), "6 * 8 || 6 * 15");
+        assert!(equations[2].solve(EXTENDED).is_none());
+
+        let equations = Solver::parse_input("12: 17 5\n".to_string()).unwrap();
+        assert!(equations[0].solve(EXTENDED).is_none());
+        let operators: &[&dyn Operator] = &[&super::Add, &super::Mul, &Sub];
+        let witness = equations[0].solve(operators).unwrap();
+        assert_eq!(witness.to_string(), "17 - 5");
+
+        let equations = Solver::parse_input("0: 5 0\n5: 0 5\n0: 3 4 0\n".to_string()).unwrap();
+        assert_eq!(find_total_valid_sum(&equations, BASIC), 5);
+        assert_eq!(equations[0].solve(BASIC).unwrap().to_string(), "5 * 0");
+        assert_eq!(equations[2].solve(BASIC).unwrap().to_string(), "3 + 4 * 0");
+        let concat: &[&dyn Operator] = &[&super::Concat];
+        assert_eq!(equations[1].solve(concat).unwrap().to_string(), "0 || 5");
+    }
+}
@@ -1,9 +1,11 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
 
-use crate::common::Position;
+use crate::{common::Position, display::Frame, math::gcd};
 
-use failure::Error;
-use num::integer::gcd;
+use ansi_term::Colour;
+use failure::{err_msg, Error};
+use itertools::Itertools;
+use num::rational::Ratio;
 
 pub struct Grid {
     antennas: HashMap<char, Vec<Position>>,
@@ -15,6 +17,41 @@ impl Grid {
     fn inside(&self, position: Position) -> bool {
         (0..self.width).contains(&position.x) && (0..self.height).contains(&position.y)
     }
+
+    // The positions on the grid along the line through point in the direction of step.
+    // Where the line enters and leaves the grid is worked out exactly, so the point
+    // itself doesn't need to be on the grid.
+    fn points_on_line(&self, point: Position, step: Position) -> impl Iterator<Item = Position> {
+        let mut lower: Option<Ratio<i64>> = None;
+        let mut upper: Option<Ratio<i64>> = None;
+        let mut missed = false;
+
+        for (start, delta, size) in [
+            (point.x, step.x, self.width),
+            (point.y, step.y, self.height),
+        ] {
+            if delta == 0 {
+                missed |= !(0..size).contains(&start);
+            } else {
+                let first = Ratio::new(-start, delta);
+                let last = Ratio::new(size - 1 - start, delta);
+                let (enter, leave) = (first.min(last), first.max(last));
+                lower = lower.max(Some(enter));
+                upper = Some(upper.map_or(leave, |upper| upper.min(leave)));
+            }
+        }
+
+        let steps = match (lower, upper) {
+            (Some(lower), Some(upper)) if !missed => {
+                Some(lower.ceil().to_integer()..=upper.floor().to_integer())
+            }
+            _ => None,
+        };
+        steps
+            .into_iter()
+            .flatten()
+            .map(move |offset| point + step * offset)
+    }
 }
 
 trait AntinodeFinder {
@@ -25,6 +62,7 @@ trait AntinodeFinder {
     ) -> impl Iterator<Item = Position> + '_;
 }
 
+// Points in line with two antennas where one is twice as far away as the other.
 struct BasicFinder {}
 
 impl AntinodeFinder for BasicFinder {
@@ -34,13 +72,20 @@ impl AntinodeFinder for BasicFinder {
         right: Position,
     ) -> impl Iterator<Item = Position> + '_ {
         let diff = right - left;
-        assert!(diff.x % 3 != 0 || diff.y % 3 != 0);
-        [left - diff, right + diff]
-            .into_iter()
-            .filter(|&pos| grid.inside(pos))
+        let mut antinodes = vec![left - diff, right + diff];
+
+        // The points a third of the way from each antenna to the other are also twice as
+        // far from one as the other, if they're on the grid.
+        if diff.x % 3 == 0 && diff.y % 3 == 0 {
+            antinodes.push(left + diff / 3);
+            antinodes.push(right - diff / 3);
+        }
+
+        antinodes.into_iter().filter(|&pos| grid.inside(pos))
     }
 }
 
+// Every grid point on the line through two antennas.
 struct FullFinder {}
 
 impl AntinodeFinder for FullFinder {
@@ -49,16 +94,8 @@ impl AntinodeFinder for FullFinder {
         left: Position,
         right: Position,
     ) -> impl Iterator<Item = Position> + '_ {
-        let step = right - left;
-        assert!(gcd(step.x, step.y) == 1);
-        (0..)
-            .map(move |offset: i64| left - step * offset)
-            .take_while(|&pos| grid.inside(pos))
-            .chain(
-                (1..)
-                    .map(move |offset: i64| left + step * offset)
-                    .take_while(|&pos| grid.inside(pos)),
-            )
+        let diff = right - left;
+        grid.points_on_line(left, diff / gcd(diff.x, diff.y))
     }
 }
 
@@ -72,14 +109,79 @@ fn find_antinodes<'a, F: AntinodeFinder>(
     })
 }
 
+// The frequencies of the antennas that cause each antinode, only looking at the given
+// frequencies if there are any.
+fn find_antinode_origins<F: AntinodeFinder>(
+    grid: &Grid,
+    frequencies: Option<&HashSet<char>>,
+) -> HashMap<Position, BTreeSet<char>> {
+    let mut origins: HashMap<Position, BTreeSet<char>> = HashMap::new();
+
+    for (&frequency, positions) in &grid.antennas {
+        if frequencies.is_some_and(|frequencies| !frequencies.contains(&frequency)) {
+            continue;
+        }
+
+        for antinode in find_antinodes::<F>(grid, positions) {
+            origins.entry(antinode).or_default().insert(frequency);
+        }
+    }
+
+    origins
+}
+
 fn count_antinodes<F: AntinodeFinder>(grid: &Grid) -> usize {
-    let antinodes: HashSet<Position> = grid
-        .antennas
-        .iter()
-        .flat_map(|(_, positions)| find_antinodes::<F>(grid, positions))
-        .collect();
+    find_antinode_origins::<F>(grid, None).len()
+}
+
+// The grid with each antinode shown as the frequency that caused it, or '#' if it was
+// caused by more than one.  Antennas are drawn over any antinodes.
+fn antinode_frame(grid: &Grid, origins: &HashMap<Position, BTreeSet<char>>) -> Frame {
+    let mut frame = Frame::new(Position {
+        x: grid.width,
+        y: grid.height,
+    });
 
-    antinodes.len()
+    for (&position, frequencies) in origins {
+        match frequencies.iter().exactly_one() {
+            Ok(&frequency) => frame.set(position, frequency, Colour::Green.normal()),
+            Err(_) => frame.set(position, '#', Colour::Red.bold()),
+        }
+    }
+
+    for (&frequency, positions) in &grid.antennas {
+        frame.draw(positions, frequency, Colour::White.bold());
+    }
+
+    frame
+}
+
+pub fn antinodes_input(
+    data: String,
+    frequencies: Option<&str>,
+    resonant: bool,
+) -> Result<String, Error> {
+    let grid = <Solver as super::Solver>::parse_input(data)?;
+
+    let frequencies: Option<HashSet<char>> =
+        frequencies.map(|frequencies| frequencies.chars().collect());
+    if let Some(frequencies) = &frequencies {
+        if let Some(missing) = frequencies
+            .iter()
+            .find(|frequency| !grid.antennas.contains_key(frequency))
+        {
+            return Err(err_msg(format!("No antennas with frequency {}", missing)));
+        }
+    }
+
+    let origins = if resonant {
+        find_antinode_origins::<FullFinder>(&grid, frequencies.as_ref())
+    } else {
+        find_antinode_origins::<BasicFinder>(&grid, frequencies.as_ref())
+    };
+
+    let frame = antinode_frame(&grid, &origins).caption(format!("{} antinodes", origins.len()));
+    Ok(frame.to_string())
 }
 
 pub struct Solver {}
@@ -116,3 +218,70 @@ impl super::Solver for Solver {
         (Some(part1.to_string()), Some(part2.to_string()))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use super::{
+        antinode_frame, count_antinodes, find_antinode_origins, BasicFinder, FullFinder, Solver,
+    };
+    use crate::common::Position;
+    use crate::Solver as _;
+
+    const EXAMPLE: &str = "............
+........0...
+.....0......
+.......0....
+....0.......
+......A.....
+............
+............
+........A...
+.........A..
+............
+............";
+
+    #[test]
+    fn test_example() {
+        let grid = Solver::parse_input(EXAMPLE.to_string()).unwrap();
+        assert_eq!(count_antinodes::<BasicFinder>(&grid), 14);
+        assert_eq!(count_antinodes::<FullFinder>(&grid), 34);
+
+        let only_a: HashSet<char> = "A".chars().collect();
+        let origins = find_antinode_origins::<BasicFinder>(&grid, Some(&only_a));
+        assert_eq!(origins.len(), 5);
+
+        // One antinode is caused by both frequencies, and one is under an antenna.
+        let origins = find_antinode_origins::<BasicFinder>(&grid, None);
+        let frame = antinode_frame(&grid, &origins);
+        let symbols: Vec<_> = frame.cells().map(|(_, symbol, _)| symbol).collect();
+        assert_eq!(symbols.iter().filter(|&&symbol| symbol == '#').count(), 1);
+        assert_eq!(
+            symbols.iter().filter(|&&symbol| symbol == '0').count(),
+            4 + 8
+        );
+    }
+
+    #[test]
+    fn test_geometry() {
+        // Antennas a multiple of three apart have antinodes between them too.
+        let grid = Solver::parse_input("a.....\n......\n......\n...a..".to_string()).unwrap();
+        let origins = find_antinode_origins::<BasicFinder>(&grid, None);
+        let mut antinodes: Vec<_> = origins.keys().map(|pos| (pos.x, pos.y)).collect();
+        antinodes.sort();
+        assert_eq!(antinodes, vec![(1, 1), (2, 2)]);
+
+        // Every grid point on the line, even between antennas that aren't next to each
+        // other on it.
+        let grid =
+            Solver::parse_input("a.....\n......\n..a...\n......\n......".to_string()).unwrap();
+        assert_eq!(count_antinodes::<FullFinder>(&grid), 5);
+
+        let points: Vec<_> = grid
+            .points_on_line(Position { x: -3, y: 6 }, Position { x: 1, y: -1 })
+            .map(|pos| (pos.x, pos.y))
+            .collect();
+        assert_eq!(points, vec![(0, 3), (1, 2), (2, 1), (3, 0)]);
+    }
+}
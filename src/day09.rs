@@ -1,67 +1,158 @@
-use failure::Error;
-use std::cmp::min;
+use failure::{err_msg, Error};
+use std::cmp::{min, Reverse};
+use std::collections::{BinaryHeap, VecDeque};
+use std::iter::once;
 use std::ops::Range;
 
 trait Defrag {
-    fn defragment(segments: &mut Vec<Segment>);
+    fn defragment(disk: &mut Disk);
 }
 
+// Moves individual blocks from the end of the disk into the leftmost free space.
 struct BlockDefrag {}
 
 impl Defrag for BlockDefrag {
-    fn defragment(segments: &mut Vec<Segment>) {
-        let mut index = 0;
-        while index < segments.len() - 1 {
-            let space = segments[index + 1].range.start - segments[index].range.end;
-            if space != 0 {
-                let next_free = segments[index].range.end;
-                let new_segment = segments.last_mut().unwrap().split_to(next_free, space);
-                segments.insert(index + 1, new_segment);
-                if segments.last().unwrap().range.is_empty() {
-                    segments.pop();
-                }
+    fn defragment(disk: &mut Disk) {
+        let mut free: VecDeque<_> = disk.free_spans().into();
+        let mut moved = vec![];
+
+        disk.segments.sort_by_key(|segment| segment.range.start);
+        while let Some(mut segment) = disk.segments.pop() {
+            let Some(span) = free
+                .front_mut()
+                .filter(|span| span.start < segment.range.start)
+            else {
+                disk.segments.push(segment);
+                break;
+            };
+
+            let amount = min(span.end - span.start, segment.size());
+            moved.push(Segment {
+                file_id: segment.file_id,
+                range: span.start..span.start + amount,
+            });
+
+            span.start += amount;
+            if span.is_empty() {
+                free.pop_front();
             }
 
-            index += 1;
+            segment.range.end -= amount;
+            if !segment.range.is_empty() {
+                disk.segments.push(segment);
+            }
         }
+
+        disk.segments.extend(moved);
     }
 }
 
+// Moves whole files, starting from the end of the disk, into the leftmost free span
+// they fit in.
 struct FileDefrag {}
 
 impl Defrag for FileDefrag {
-    fn defragment(segments: &mut Vec<Segment>) {
-        for file_id in (0..segments.len() as u64).rev() {
-            let segment_index = segments
-                .iter()
-                .enumerate()
-                .find_map(|(index, segment)| {
-                    if segment.file_id == file_id {
-                        Some(index)
-                    } else {
-                        None
-                    }
-                })
-                .unwrap();
-            let segment = segments[segment_index].clone();
+    fn defragment(disk: &mut Disk) {
+        move_files(disk, FreeSpans::first_fit);
+    }
+}
+
+// Moves whole files into the smallest free span they fit in, to leave larger spans for
+// larger files.
+struct BestFitDefrag {}
+
+impl Defrag for BestFitDefrag {
+    fn defragment(disk: &mut Disk) {
+        move_files(disk, FreeSpans::best_fit);
+    }
+}
 
-            for index in 0..segment_index {
-                let space = segments[index + 1].range.start - segments[index].range.end;
+// Moves whole files towards the end of the disk instead of the start.
+struct EndDefrag {}
 
-                if space >= segment.size() {
-                    let next_free = segments[index].range.end;
-                    let new_segment = segment.move_to(next_free);
-                    segments.remove(segment_index);
-                    segments.insert(index + 1, new_segment);
+impl Defrag for EndDefrag {
+    fn defragment(disk: &mut Disk) {
+        let mut mirrored = disk.mirrored();
+        FileDefrag::defragment(&mut mirrored);
+        *disk = mirrored.mirrored();
+    }
+}
 
-                    break;
-                }
-            }
+// The start of each free span, in a min-heap per span size.
+struct FreeSpans {
+    by_size: Vec<BinaryHeap<Reverse<u64>>>,
+}
+
+impl FreeSpans {
+    fn new(spans: &[Range<u64>]) -> Self {
+        let max_size = spans.iter().map(|span| span.end - span.start).max();
+        let mut free = FreeSpans {
+            by_size: vec![BinaryHeap::new(); max_size.map_or(0, |size| size as usize + 1)],
+        };
+
+        for span in spans {
+            free.add(span.start, span.end - span.start);
         }
+
+        free
+    }
+
+    fn add(&mut self, start: u64, size: u64) {
+        if size > 0 {
+            self.by_size[size as usize].push(Reverse(start));
+        }
+    }
+
+    // The starts of the leftmost spans of each size that can fit a file, and are before
+    // the limit.
+    fn candidates(&self, size: u64, limit: u64) -> impl Iterator<Item = (u64, u64)> + '_ {
+        self.by_size
+            .iter()
+            .enumerate()
+            .skip(size as usize)
+            .filter_map(|(span_size, heap)| {
+                heap.peek().map(|&Reverse(start)| (start, span_size as u64))
+            })
+            .filter(move |&(start, _)| start < limit)
+    }
+
+    fn first_fit(&self, size: u64, limit: u64) -> Option<u64> {
+        self.candidates(size, limit)
+            .min()
+            .map(|(_, span_size)| span_size)
+    }
+
+    fn best_fit(&self, size: u64, limit: u64) -> Option<u64> {
+        self.candidates(size, limit)
+            .next()
+            .map(|(_, span_size)| span_size)
+    }
+
+    // Takes the leftmost span of the given size, returning what's left of it to the pool.
+    fn take(&mut self, span_size: u64, size: u64) -> u64 {
+        let Reverse(start) = self.by_size[span_size as usize].pop().unwrap();
+        self.add(start + size, span_size - size);
+        start
     }
 }
 
-#[derive(Clone, Debug)]
+// Moves each file at most once, from the end of the disk, into the span picked by choose.
+// The space a file leaves behind is never needed, since anything that could move into it
+// is already to its left.
+fn move_files(disk: &mut Disk, choose: fn(&FreeSpans, u64, u64) -> Option<u64>) {
+    let mut free = FreeSpans::new(&disk.free_spans());
+
+    disk.segments
+        .sort_by_key(|segment| Reverse(segment.range.start));
+    for segment in disk.segments.iter_mut() {
+        if let Some(span_size) = choose(&free, segment.size(), segment.range.start) {
+            let start = free.take(span_size, segment.size());
+            *segment = segment.move_to(start);
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Segment {
     file_id: u64,
     range: Range<u64>,
@@ -76,62 +167,191 @@ impl Segment {
         self.range.end - self.range.start
     }
 
-    fn split_to(&mut self, position: u64, max_amount: u64) -> Self {
-        let amount = min(max_amount, self.size());
-        self.range.end -= amount;
+    fn move_to(&self, position: u64) -> Self {
         Segment {
             file_id: self.file_id,
-            range: position..position + amount,
+            range: position..position + self.size(),
         }
     }
+}
 
-    fn move_to(&self, position: u64) -> Self {
-        Segment {
-            file_id: self.file_id,
-            range: position..position + self.size(),
+#[derive(Clone, Debug)]
+pub struct Disk {
+    segments: Vec<Segment>,
+    size: u64,
+}
+
+impl Disk {
+    fn free_spans(&self) -> Vec<Range<u64>> {
+        let mut ranges: Vec<_> = self
+            .segments
+            .iter()
+            .map(|segment| segment.range.clone())
+            .collect();
+        ranges.sort_by_key(|range| range.start);
+
+        let mut spans = vec![];
+        let mut position = 0;
+        for range in ranges.into_iter().chain(once(self.size..self.size)) {
+            if range.start > position {
+                spans.push(position..range.start);
+            }
+            position = range.end;
         }
+
+        spans
+    }
+
+    fn checksum(&self) -> u64 {
+        self.segments.iter().map(|segment| segment.checksum()).sum()
+    }
+
+    // The same disk, back to front.
+    fn mirrored(&self) -> Self {
+        Disk {
+            segments: self
+                .segments
+                .iter()
+                .map(|segment| Segment {
+                    file_id: segment.file_id,
+                    range: self.size - segment.range.end..self.size - segment.range.start,
+                })
+                .collect(),
+            size: self.size,
+        }
+    }
+
+    // Shows each block as the last digit of its file id, or '.' if it's free.
+    fn render(&self) -> String {
+        let mut blocks = vec!['.'; self.size as usize];
+        for segment in &self.segments {
+            let digit = char::from_digit((segment.file_id % 10) as u32, 10).unwrap();
+            for index in segment.range.clone() {
+                blocks[index as usize] = digit;
+            }
+        }
+        blocks.into_iter().collect()
     }
 }
 
-fn calculate_checksum(segments: &[Segment]) -> u64 {
-    segments.iter().map(|segment| segment.checksum()).sum()
+fn defragmented_checksum<D: Defrag>(mut disk: Disk) -> u64 {
+    D::defragment(&mut disk);
+    disk.checksum()
 }
 
-fn defragmented_checksum<D: Defrag>(mut segments: Vec<Segment>) -> u64 {
-    D::defragment(&mut segments);
-    calculate_checksum(&segments)
+// Shows the disk before and after defragmenting it with the named strategy.
+pub fn defrag_input(data: String, strategy: &str) -> Result<String, Error> {
+    let disk = <Solver as super::Solver>::parse_input(data)?;
+
+    let defragment = match strategy {
+        "block" => BlockDefrag::defragment,
+        "file" => FileDefrag::defragment,
+        "best-fit" => BestFitDefrag::defragment,
+        "end" => EndDefrag::defragment,
+        _ => {
+            return Err(err_msg(format!(
+                "Unknown strategy {}, expected block, file, best-fit or end",
+                strategy
+            )))
+        }
+    };
+
+    let mut defragmented = disk.clone();
+    defragment(&mut defragmented);
+
+    Ok(format!(
+        "{}\n{}\nChecksum: {}",
+        disk.render(),
+        defragmented.render(),
+        defragmented.checksum()
+    ))
 }
 
 pub struct Solver {}
 
 impl super::Solver for Solver {
-    type Problem = Vec<Segment>;
+    type Problem = Disk;
 
     fn parse_input(data: String) -> Result<Self::Problem, Error> {
-        Ok(data
-            .trim()
-            .char_indices()
-            .scan(0u64, |pos, (index, digit)| {
-                let width = digit.to_digit(10).unwrap() as u64;
-                let start = *pos;
-                *pos += width;
-
-                if index % 2 == 0 {
-                    Some(Some(Segment {
-                        file_id: index as u64 / 2,
-                        range: start..start + width,
-                    }))
-                } else {
-                    Some(None)
-                }
-            })
-            .flatten()
-            .collect())
+        let mut segments = vec![];
+        let mut position = 0;
+
+        for (index, digit) in data.trim().char_indices() {
+            let width = digit
+                .to_digit(10)
+                .ok_or_else(|| err_msg(format!("Invalid size {}", digit)))?
+                as u64;
+
+            if index % 2 == 0 {
+                segments.push(Segment {
+                    file_id: index as u64 / 2,
+                    range: position..position + width,
+                });
+            }
+            position += width;
+        }
+
+        Ok(Disk {
+            segments,
+            size: position,
+        })
     }
 
-    fn solve(segments: Self::Problem) -> (Option<String>, Option<String>) {
-        let part1 = defragmented_checksum::<BlockDefrag>(segments.clone());
-        let part2 = defragmented_checksum::<FileDefrag>(segments);
+    fn solve(disk: Self::Problem) -> (Option<String>, Option<String>) {
+        let part1 = defragmented_checksum::<BlockDefrag>(disk.clone());
+        let part2 = defragmented_checksum::<FileDefrag>(disk);
         (Some(part1.to_string()), Some(part2.to_string()))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{BestFitDefrag, BlockDefrag, Defrag, Disk, EndDefrag, FileDefrag, Solver};
+    use crate::Solver as _;
+
+    fn defragmented<D: Defrag>(data: &str) -> Disk {
+        let mut disk = Solver::parse_input(data.to_string()).unwrap();
+        D::defragment(&mut disk);
+        disk
+    }
+
+    #[test]
+    fn test_example() {
+        let disk = Solver::parse_input("12345".to_string()).unwrap();
+        assert_eq!(disk.render(), "0..111....22222");
+        assert_eq!(
+            defragmented::<BlockDefrag>("12345").render(),
+            "022111222......"
+        );
+
+        let disk = defragmented::<BlockDefrag>("2333133121414131402");
+        assert_eq!(disk.render(), "0099811188827773336446555566..............");
+        assert_eq!(disk.checksum(), 1928);
+
+        let disk = defragmented::<FileDefrag>("2333133121414131402");
+        assert_eq!(disk.render(), "00992111777.44.333....5555.6666.....8888..");
+        assert_eq!(disk.checksum(), 2858);
+    }
+
+    #[test]
+    fn test_strategies() {
+        assert_eq!(defragmented::<FileDefrag>("13222").render(), "022.11....");
+        assert_eq!(
+            defragmented::<BestFitDefrag>("13222").render(),
+            "011...22.."
+        );
+        assert_eq!(defragmented::<EndDefrag>("13222").render(), "....11.022");
+
+        let disk = Solver::parse_input("2333133121414131402".to_string()).unwrap();
+        for defragmented in [
+            defragmented::<BestFitDefrag>("2333133121414131402"),
+            defragmented::<EndDefrag>("2333133121414131402"),
+        ] {
+            let mut blocks: Vec<_> = defragmented.render().chars().collect();
+            let mut expected: Vec<_> = disk.render().chars().collect();
+            blocks.sort();
+            expected.sort();
+            assert_eq!(blocks, expected);
+        }
+    }
+}
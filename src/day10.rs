@@ -1,103 +1,44 @@
-use std::{
-    collections::{HashMap, HashSet},
-    str::FromStr,
-};
+use std::path::Path;
 
-use crate::common::Position;
+use crate::display::{Frame, Player};
+use crate::export;
+use crate::height_map::Map;
 use failure::{err_msg, Error};
 
-pub struct Map {
-    heights: Vec<Vec<u8>>,
+fn total_trailhead_score(map: &Map) -> usize {
+    map.trailheads().map(|pos| map.trailhead_score(pos)).sum()
 }
 
-impl FromStr for Map {
-    type Err = Error;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        s.lines()
-            .map(|line| {
-                line.chars()
-                    .map(|c| {
-                        c.to_digit(10)
-                            .map(|d| d as u8)
-                            .ok_or_else(|| err_msg(format!("Invalid digit: {}", c)))
-                    })
-                    .collect()
-            })
-            .collect::<Result<_, _>>()
-            .map(|heights| Map { heights })
-    }
+fn total_trailhead_rating(map: &Map) -> usize {
+    map.trailheads().map(|pos| map.trailhead_rating(pos)).sum()
 }
 
-impl Map {
-    fn all_positions(&self) -> impl Iterator<Item = Position> + '_ {
-        self.heights
-            .iter()
-            .enumerate()
-            .flat_map(|(y, row)| (0..row.len()).map(move |x| (x, y).into()))
-    }
-
-    fn height_at(&self, position: Position) -> Option<u8> {
-        self.heights
-            .get(position.y as usize)
-            .and_then(|row| row.get(position.x as usize).cloned())
-    }
-
-    fn trailhead_score(&self, position: Position) -> usize {
-        if self.height_at(position) != Some(0) {
-            return 0;
-        }
-
-        let mut reachable = HashSet::new();
-        reachable.insert(position);
-
-        for height in 1..=9 {
-            reachable = reachable
-                .into_iter()
-                .flat_map(|pos| pos.adjacent())
-                .filter(|&pos| self.height_at(pos) == Some(height))
-                .collect();
-        }
-
-        reachable.len()
-    }
-
-    fn trailhead_rating(&self, position: Position) -> usize {
-        if self.height_at(position) != Some(0) {
-            return 0;
-        }
-
-        let mut reachable = HashMap::new();
-        reachable.insert(position, 1);
-
-        for height in 1..=9 {
-            let mut new_reachable = HashMap::new();
-
-            for (pos, count) in reachable.into_iter() {
-                for adj_pos in pos.adjacent() {
-                    if self.height_at(adj_pos) == Some(height) {
-                        *new_reachable.entry(adj_pos).or_default() += count;
-                    }
-                }
-            }
-
-            reachable = new_reachable;
-        }
-
-        reachable.values().sum()
-    }
+// Each trail from the trailhead with the highest rating in turn.
+fn trail_frames(map: &Map) -> Result<impl Iterator<Item = Frame> + '_, Error> {
+    let trailhead = map
+        .trailheads()
+        .max_by_key(|&pos| map.trailhead_rating(pos))
+        .ok_or_else(|| err_msg("No trailheads on the map"))?;
+
+    Ok(map
+        .trails(trailhead)
+        .enumerate()
+        .map(move |(index, trail)| {
+            map.highlight(&trail)
+                .caption(format!("Trail {} from {}", index + 1, trailhead))
+        }))
 }
 
-fn total_trailhead_score(map: &Map) -> usize {
-    map.all_positions()
-        .map(|pos| map.trailhead_score(pos))
-        .sum()
+pub fn visualize_input(data: String) -> Result<(), Error> {
+    let map = <Solver as super::Solver>::parse_input(data)?;
+    let frames = trail_frames(&map)?;
+    Player::new(2).play(frames)
 }
 
-fn total_trailhead_rating(map: &Map) -> usize {
-    map.all_positions()
-        .map(|pos| map.trailhead_rating(pos))
-        .sum()
+pub fn export_input(data: String, path: &Path, max_frames: usize) -> Result<(), Error> {
+    let map = <Solver as super::Solver>::parse_input(data)?;
+    let frames = trail_frames(&map)?;
+    export::save(path, frames, max_frames)
 }
 
 pub struct Solver {}
@@ -115,3 +56,51 @@ impl super::Solver for Solver {
         (Some(part1.to_string()), Some(part2.to_string()))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{total_trailhead_rating, total_trailhead_score, Solver};
+    use crate::height_map::Rules;
+    use crate::Solver as _;
+
+    const EXAMPLE: &str = "\
+89010123
+78121874
+87430965
+96549874
+45678903
+32019012
+01329801
+10456732
+";
+
+    #[test]
+    fn test_trailheads() {
+        let map = Solver::parse_input(EXAMPLE.to_string()).unwrap();
+        assert_eq!(total_trailhead_score(&map), 36);
+        assert_eq!(total_trailhead_rating(&map), 81);
+
+        // Walking down from the summits finds the same trails the other way round.
+        let map = map.with_rules(Rules::default().descending());
+        assert_eq!(total_trailhead_score(&map), 36);
+        assert_eq!(total_trailhead_rating(&map), 81);
+
+        // Going up by one to three at a time finds many more trails.
+        let map = map.with_rules(Rules {
+            climb: 2,
+            tolerance: 1,
+            ..Rules::default()
+        });
+        assert_eq!(map.trailheads().count(), 9);
+        assert_eq!(
+            (total_trailhead_score(&map), total_trailhead_rating(&map)),
+            (43, 208)
+        );
+        assert_eq!(
+            map.trailheads()
+                .map(|pos| map.trails(pos).count())
+                .sum::<usize>(),
+            208
+        );
+    }
+}
@@ -1,14 +1,15 @@
-use std::collections::HashMap;
+use std::collections::{hash_map::Entry, HashMap};
 
 use failure::{err_msg, Error};
-use itertools::Either;
+use itertools::{Either, Itertools};
+use num::{BigUint, One};
 
 fn updated_stones(stone: u64) -> impl Iterator<Item = u64> {
     if stone == 0 {
         Either::Left([1].into_iter())
     } else {
         let width = stone.ilog10() + 1;
-        if width % 2 == 0 {
+        if width.is_multiple_of(2) {
             let base = 10u64.pow(width / 2);
             Either::Right([stone / base, stone % base].into_iter())
         } else {
@@ -17,25 +18,126 @@ fn updated_stones(stone: u64) -> impl Iterator<Item = u64> {
     }
 }
 
-fn count_stones(init_stones: Vec<u64>, blinks: usize) -> usize {
-    let mut stones = HashMap::new();
-    for stone in init_stones {
-        *stones.entry(stone).or_default() += 1;
+// The stone values reachable from some starting stones, which is a small closed set, with
+// the stones each value turns into.
+pub struct StoneEngine {
+    values: Vec<u64>,
+    indices: HashMap<u64, usize>,
+    children: Vec<Vec<usize>>,
+    blinks: usize,
+    counts: Vec<BigUint>,
+}
+
+// Once the set of distinct stone values on the line starts repeating, every blink after
+// that cycles through the same sets.
+#[derive(Debug, PartialEq, Eq)]
+pub struct SteadyState {
+    blinks: usize,
+    period: usize,
+    values: Vec<u64>,
+}
+
+impl StoneEngine {
+    fn new(stones: &[u64]) -> Self {
+        let mut values = vec![];
+        let mut indices = HashMap::new();
+        let mut to_visit: Vec<u64> = stones.to_vec();
+
+        while let Some(stone) = to_visit.pop() {
+            if let Entry::Vacant(entry) = indices.entry(stone) {
+                entry.insert(values.len());
+                values.push(stone);
+                to_visit.extend(updated_stones(stone));
+            }
+        }
+
+        let children = values
+            .iter()
+            .map(|&stone| updated_stones(stone).map(|child| indices[&child]).collect())
+            .collect();
+
+        StoneEngine {
+            counts: vec![BigUint::one(); values.len()],
+            values,
+            indices,
+            children,
+            blinks: 0,
+        }
+    }
+
+    // How many stones a single stone turns into after some blinks.  Each blink's counts
+    // for every value are built from the previous blink's, and the latest are kept so
+    // later queries can carry on from them.
+    fn count(&mut self, stone: u64, blinks: usize) -> BigUint {
+        if blinks < self.blinks {
+            self.counts = vec![BigUint::one(); self.values.len()];
+            self.blinks = 0;
+        }
+
+        while self.blinks < blinks {
+            self.counts = self
+                .children
+                .iter()
+                .map(|children| children.iter().map(|&child| &self.counts[child]).sum())
+                .collect();
+            self.blinks += 1;
+        }
+
+        self.counts[self.indices[&stone]].clone()
     }
 
-    for _ in 0..blinks {
-        let mut new_stones = HashMap::new();
+    fn count_stones(&mut self, stones: &[u64], blinks: usize) -> BigUint {
+        stones.iter().map(|&stone| self.count(stone, blinks)).sum()
+    }
 
-        for (stone, count) in stones {
-            for new_stone in updated_stones(stone) {
-                *new_stones.entry(new_stone).or_default() += count;
+    fn steady_state(&self, stones: &[u64]) -> SteadyState {
+        let mut present = vec![false; self.values.len()];
+        for stone in stones {
+            present[self.indices[stone]] = true;
+        }
+
+        let mut seen = HashMap::new();
+        for blinks in 0.. {
+            if let Some(first) = seen.insert(present.clone(), blinks) {
+                let mut values: Vec<_> = present
+                    .iter()
+                    .zip(&self.values)
+                    .filter(|&(&present, _)| present)
+                    .map(|(_, &value)| value)
+                    .collect();
+                values.sort();
+
+                return SteadyState {
+                    blinks: first,
+                    period: blinks - first,
+                    values,
+                };
+            }
+
+            let mut next = vec![false; self.values.len()];
+            for (index, _) in present.iter().enumerate().filter(|&(_, &present)| present) {
+                for &child in &self.children[index] {
+                    next[child] = true;
+                }
             }
+            present = next;
         }
 
-        stones = new_stones;
+        unreachable!()
     }
+}
 
-    stones.values().sum()
+// When the stone values on the line settle down, and what they settle down to.
+pub fn analyze_input(data: String) -> Result<String, Error> {
+    let stones = <Solver as super::Solver>::parse_input(data)?;
+    let steady_state = StoneEngine::new(&stones).steady_state(&stones);
+    Ok(format!(
+        "{} distinct stone values after {} blinks, repeating every {}:\n{}",
+        steady_state.values.len(),
+        steady_state.blinks,
+        steady_state.period,
+        steady_state.values.iter().join(" ")
+    ))
 }
 
 pub struct Solver {}
@@ -53,8 +155,41 @@ impl super::Solver for Solver {
     }
 
     fn solve(stones: Self::Problem) -> (Option<String>, Option<String>) {
-        let part1 = count_stones(stones.clone(), 25);
-        let part2 = count_stones(stones, 75);
+        let mut engine = StoneEngine::new(&stones);
+        let part1 = engine.count_stones(&stones, 25);
+        let part2 = engine.count_stones(&stones, 75);
         (Some(part1.to_string()), Some(part2.to_string()))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use num::BigUint;
+
+    use super::StoneEngine;
+
+    #[test]
+    fn test_count_stones() {
+        let stones = [125, 17];
+        let mut engine = StoneEngine::new(&stones);
+
+        assert_eq!(engine.count_stones(&stones, 6), BigUint::from(22u32));
+        assert_eq!(engine.count_stones(&stones, 25), BigUint::from(55312u32));
+        assert_eq!(engine.count_stones(&[0], 4), BigUint::from(4u32));
+
+        // Far more than fits in a u128.
+        let count = engine.count_stones(&stones, 1000);
+        assert!(count.bits() > 128);
+    }
+
+    #[test]
+    fn test_steady_state() {
+        let stones = [0];
+        let engine = StoneEngine::new(&stones);
+        let steady_state = engine.steady_state(&stones);
+
+        assert_eq!(steady_state.period, 1);
+        assert_eq!(steady_state.values.len(), 54);
+        assert!(steady_state.values.contains(&2024));
+    }
+}
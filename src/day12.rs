@@ -1,132 +1,46 @@
-use crate::common::{Direction, Position};
-use failure::Error;
-use itertools::iproduct;
-use std::collections::{HashMap, HashSet};
-
-#[derive(Default, Debug, Clone)]
-struct Region {
-    plots: HashSet<Position>,
+use crate::common::Position;
+use crate::regions::{find_regions, to_svg, Region};
+use failure::{err_msg, Error};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+trait Pricing {
+    fn price(region: &Region) -> usize;
 }
 
-impl Region {
-    fn add(&mut self, position: Position) {
-        self.plots.insert(position);
-    }
-
-    fn contains(&self, position: Position) -> bool {
-        self.plots.contains(&position)
-    }
-
-    fn area(&self) -> usize {
-        self.plots.len()
-    }
-
-    fn perimeter(&self) -> usize {
-        iproduct!(self.plots.iter(), Direction::cardinal())
-            .filter(|&(pos, dir)| !self.contains(pos.step(dir)))
-            .count()
-    }
-
-    fn sides(&self) -> usize {
-        let mut to_visit: HashSet<Position> = self
-            .plots
-            .iter()
-            .flat_map(|pos| pos.adjacent())
-            .collect::<HashSet<_>>()
-            .difference(&self.plots)
-            .cloned()
-            .collect();
-
-        let mut sides = 0;
-
-        while let Some(start) = to_visit.iter().next().cloned() {
-            let start_dir = Direction::cardinal()
-                .find(|&dir| self.plots.contains(&start.step(dir)))
-                .unwrap()
-                .turn_left();
-
-            let mut pos = start;
-            let mut dir = start_dir;
-
-            loop {
-                to_visit.remove(&pos);
-
-                let next_pos = pos.step(dir);
-
-                if self.contains(next_pos) {
-                    sides += 1;
-                    dir = dir.turn_left();
-                } else if !self.contains(next_pos.step(dir.turn_right())) {
-                    sides += 1;
-                    dir = dir.turn_right();
-                    pos = next_pos.step(dir);
-                } else {
-                    pos = next_pos;
-                }
-
-                if pos == start && dir == start_dir {
-                    break;
-                }
-            }
-        }
-
-        sides
-    }
-
-    fn full_price(&self) -> usize {
-        self.area() * self.perimeter()
-    }
+struct FullPrice {}
 
-    fn discounted_price(&self) -> usize {
-        self.area() * self.sides()
+impl Pricing for FullPrice {
+    fn price(region: &Region) -> usize {
+        region.area() * region.perimeter()
     }
 }
 
-fn find_region(plots: &HashMap<Position, char>, position: Position) -> Region {
-    let mut region = Region::default();
-    let plant = plots.get(&position);
-
-    let mut checked = HashSet::new();
-
-    let mut to_check = HashSet::new();
-    to_check.insert(position);
-
-    while let Some(pos) = to_check.iter().cloned().next() {
-        to_check.remove(&pos);
-        checked.insert(pos);
+// Each straight section of fence costs the same however long it is.
+struct DiscountedPrice {}
 
-        if plots.get(&pos) == plant {
-            region.add(pos);
-
-            for next_pos in pos.adjacent() {
-                if !checked.contains(&next_pos) {
-                    to_check.insert(next_pos);
-                }
-            }
-        }
+impl Pricing for DiscountedPrice {
+    fn price(region: &Region) -> usize {
+        region.area() * region.sides()
     }
-
-    region
 }
 
-fn find_regions(plots: &HashMap<Position, char>) -> Vec<Region> {
-    let mut remaining: HashSet<Position> = plots.keys().cloned().collect();
-    let mut regions = vec![];
+fn get_total_price<P: Pricing>(regions: &[Region]) -> usize {
+    regions.iter().map(P::price).sum()
+}
 
-    while let Some(position) = remaining.iter().cloned().next() {
-        let region = find_region(plots, position);
-        remaining.retain(|&pos| !region.contains(pos));
-        regions.push(region);
+pub fn export_input(data: String, path: &Path) -> Result<(), Error> {
+    if path.extension().and_then(|ext| ext.to_str()) != Some("svg") {
+        return Err(err_msg(format!(
+            "Can only export the regions to a .svg file, not {}",
+            path.display()
+        )));
     }
 
-    regions
-}
-
-fn get_total_price<F>(plots: &HashMap<Position, char>, price: F) -> usize
-where
-    F: Fn(&Region) -> usize,
-{
-    find_regions(plots).iter().map(price).sum()
+    let plots = <Solver as super::Solver>::parse_input(data)?;
+    fs::write(path, to_svg(&find_regions(&plots)))?;
+    Ok(())
 }
 
 pub struct Solver {}
@@ -143,8 +57,51 @@ impl super::Solver for Solver {
     }
 
     fn solve(plots: Self::Problem) -> (Option<String>, Option<String>) {
-        let part1 = get_total_price(&plots, Region::full_price);
-        let part2 = get_total_price(&plots, Region::discounted_price);
+        let regions = find_regions(&plots);
+        let part1 = get_total_price::<FullPrice>(&regions);
+        let part2 = get_total_price::<DiscountedPrice>(&regions);
         (Some(part1.to_string()), Some(part2.to_string()))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{get_total_price, DiscountedPrice, FullPrice, Pricing, Solver};
+    use crate::regions::{find_regions, Region};
+    use crate::Solver as _;
+
+    // Fencing off the whole bounding box instead of following the region.
+    struct BoundingBoxPrice {}
+
+    impl Pricing for BoundingBoxPrice {
+        fn price(region: &Region) -> usize {
+            let bounding_box = region.bounding_box();
+            let size = bounding_box.max - bounding_box.min;
+            region.area() * 2 * (size.x + size.y + 2) as usize
+        }
+    }
+
+    fn total_prices(data: &str) -> (usize, usize, usize) {
+        let plots = Solver::parse_input(data.to_string()).unwrap();
+        let regions = find_regions(&plots);
+        (
+            get_total_price::<FullPrice>(&regions),
+            get_total_price::<DiscountedPrice>(&regions),
+            get_total_price::<BoundingBoxPrice>(&regions),
+        )
+    }
+
+    #[test]
+    fn test_prices() {
+        assert_eq!(total_prices("AAAA\nBBCD\nBBCC\nEEEC"), (140, 80, 140));
+        assert_eq!(total_prices("OOOOO\nOXOXO\nOOOOO\nOXOXO\nOOOOO").1, 436);
+        assert_eq!(
+            total_prices("EEEEE\nEXXXX\nEEEEE\nEXXXX\nEEEEE"),
+            (692, 236, 420)
+        );
+        assert_eq!(
+            total_prices("AAAAAA\nAAABBA\nAAABBA\nABBAAA\nABBAAA\nAAAAAA").1,
+            368
+        );
+    }
+}
@@ -1,15 +1,20 @@
-use crate::common::Position;
-use failure::Error;
-use num::rational::Ratio;
+use crate::{
+    common::Position,
+    math::{solve_diophantine, solve_linear_system},
+};
+use failure::{err_msg, Error};
+use num::Integer;
 
 mod parse {
     use failure::{err_msg, Error};
     use nom::{
         bytes::complete::tag,
+        character::complete::alpha1,
         character::complete::newline,
         combinator::{all_consuming, map},
+        multi::many1,
         multi::separated_list1,
-        sequence::{delimited, preceded, separated_pair, tuple},
+        sequence::{delimited, pair, preceded, separated_pair, tuple},
         IResult,
     };
 
@@ -41,16 +46,15 @@ mod parse {
 
     fn machine(input: &str) -> IResult<&str, Machine> {
         map(
-            tuple((
-                delimited(tag("Button A: "), button, newline),
-                delimited(tag("Button B: "), button, newline),
+            pair(
+                many1(delimited(
+                    tuple((tag("Button "), alpha1, tag(": "))),
+                    button,
+                    newline,
+                )),
                 delimited(tag("Prize: "), prize, newline),
-            )),
-            |(button_a, button_b, prize)| Machine {
-                button_a,
-                button_b,
-                prize,
-            },
+            ),
+            |(buttons, prize)| Machine { buttons, prize },
         )(input)
     }
 
@@ -66,70 +70,241 @@ mod parse {
 }
 
 pub struct Machine {
-    button_a: Position,
-    button_b: Position,
+    buttons: Vec<Position>,
     prize: Position,
 }
 
-fn is_valid_presses(presses: Ratio<i64>, max_presses: Option<i64>) -> bool {
-    if presses < Ratio::ZERO {
-        return false;
+const TOKEN_COSTS: [i64; 2] = [3, 1];
+const SEARCH_LIMIT: i128 = 10000000;
+const UNLIMITED: i128 = i64::MAX as i128;
+
+#[derive(Debug, Clone, Copy)]
+struct Button {
+    delta: Position,
+    cost: i64,
+}
+
+fn cross(a: Position, b: Position) -> i64 {
+    a.x * b.y - a.y * b.x
+}
+
+// The range of k for which lo <= start + k * step <= hi.
+fn step_range(start: i128, step: i128, lo: i128, hi: i128) -> (i128, i128) {
+    if step > 0 {
+        (
+            Integer::div_ceil(&(lo - start), &step),
+            Integer::div_floor(&(hi - start), &step),
+        )
+    } else {
+        (
+            Integer::div_ceil(&(hi - start), &step),
+            Integer::div_floor(&(lo - start), &step),
+        )
     }
+}
 
-    if !presses.is_integer() {
-        return false;
+fn press_single(button: Button, target: Position, max_presses: i128) -> Option<Vec<i64>> {
+    let presses = if button.delta.x != 0 {
+        target.x / button.delta.x
+    } else if button.delta.y != 0 {
+        target.y / button.delta.y
+    } else {
+        0
+    };
+
+    (button.delta * presses == target && (0..=max_presses).contains(&(presses as i128)))
+        .then(|| vec![presses])
+}
+
+fn press_independent(a: Button, b: Button, target: Position, max: i128) -> Option<Vec<i64>> {
+    let presses = solve_linear_system(
+        &[vec![a.delta.x, b.delta.x], vec![a.delta.y, b.delta.y]],
+        &[target.x, target.y],
+    )?;
+
+    presses
+        .iter()
+        .all(|presses| presses.is_integer() && (0..=max).contains(&presses.to_integer()))
+        .then(|| {
+            presses
+                .iter()
+                .map(|presses| presses.to_integer() as i64)
+                .collect()
+        })
+}
+
+// Both buttons move along the same line, so there can be many ways to reach the target.
+// The cost changes linearly along the solutions, so the cheapest is at one end.
+fn press_collinear(a: Button, b: Button, target: Position, max: i128) -> Option<Vec<i64>> {
+    if cross(a.delta, target) != 0 || cross(b.delta, target) != 0 {
+        return None;
     }
 
-    if max_presses
-        .map(Ratio::from_integer)
-        .map(|p| presses > p)
-        .unwrap_or_default()
-    {
-        return false;
+    let ((da, db), t) = if a.delta.x != 0 || b.delta.x != 0 {
+        ((a.delta.x, b.delta.x), target.x)
+    } else {
+        ((a.delta.y, b.delta.y), target.y)
+    };
+    let solution = solve_diophantine(da, db, t)?;
+
+    let (x, y) = (solution.x as i128, solution.y as i128);
+    let (step_x, step_y) = (solution.step_x as i128, solution.step_y as i128);
+    let (min_x, max_x) = step_range(x, step_x, 0, max);
+    let (min_y, max_y) = step_range(y, step_y, 0, max);
+    let (min_k, max_k) = (min_x.max(min_y), max_x.min(max_y));
+    if min_k > max_k {
+        return None;
     }
 
-    true
+    let slope = a.cost as i128 * step_x + b.cost as i128 * step_y;
+    let k = if slope > 0 { min_k } else { max_k };
+
+    Some(vec![(x + k * step_x) as i64, (y + k * step_y) as i64])
+}
+
+// The most times a button can be pressed without overshooting the target, if every
+// button moves the same way along some axis.
+fn press_bound(button: Button, others: &[Button], target: Position, max: i128) -> Option<i128> {
+    let axis_bound = |delta: i64, target: i64, axis: fn(&Button) -> i64| {
+        let positive = delta > 0 && others.iter().all(|other| axis(other) >= 0);
+        let negative = delta < 0 && others.iter().all(|other| axis(other) <= 0);
+        (positive || negative).then(|| (target / delta).max(-1) as i128)
+    };
+
+    [
+        axis_bound(button.delta.x, target.x, |other| other.delta.x),
+        axis_bound(button.delta.y, target.y, |other| other.delta.y),
+        (max < UNLIMITED).then_some(max),
+    ]
+    .into_iter()
+    .flatten()
+    .min()
+}
+
+fn cheapest_presses(
+    buttons: &[Button],
+    target: Position,
+    max: i128,
+) -> Result<Option<Vec<i64>>, Error> {
+    let cost = |presses: &Vec<i64>| -> i128 {
+        buttons
+            .iter()
+            .zip(presses)
+            .map(|(button, &presses)| button.cost as i128 * presses as i128)
+            .sum()
+    };
+
+    Ok(match buttons {
+        [] => (target == Position::origin()).then(Vec::new),
+        [button] => press_single(*button, target, max),
+        [a, b] if cross(a.delta, b.delta) != 0 => press_independent(*a, *b, target, max),
+        [a, b] if a.delta == Position::origin() => {
+            press_single(*b, target, max).map(|presses| [vec![0], presses].concat())
+        }
+        [a, b] if b.delta == Position::origin() => {
+            press_single(*a, target, max).map(|presses| [presses, vec![0]].concat())
+        }
+        [a, b] => press_collinear(*a, *b, target, max),
+        [first, rest @ ..] => {
+            let bound = press_bound(*first, rest, target, max)
+                .ok_or_else(|| err_msg("Can't bound the number of presses of a button"))?;
+
+            let search_size = buttons[..buttons.len() - 2]
+                .iter()
+                .enumerate()
+                .map(|(index, button)| {
+                    let others: Vec<_> = buttons
+                        .iter()
+                        .enumerate()
+                        .filter(|&(other, _)| other != index)
+                        .map(|(_, &other)| other)
+                        .collect();
+                    press_bound(*button, &others, target, max).unwrap_or(UNLIMITED) + 1
+                })
+                .fold(1i128, |size, bound| size.saturating_mul(bound));
+            if search_size > SEARCH_LIMIT {
+                return Err(err_msg(format!(
+                    "Too many combinations of presses to search ({})",
+                    search_size
+                )));
+            }
+
+            let mut best: Option<Vec<i64>> = None;
+            for presses in 0..=bound as i64 {
+                let Some(rest_presses) =
+                    cheapest_presses(rest, target - first.delta * presses, max)?
+                else {
+                    continue;
+                };
+
+                let candidate = [vec![presses], rest_presses].concat();
+                if best
+                    .as_ref()
+                    .is_none_or(|best| cost(&candidate) < cost(best))
+                {
+                    best = Some(candidate);
+                }
+            }
+            best
+        }
+    })
 }
 
 impl Machine {
     fn with_prize_offset(&self, delta: Position) -> Self {
         Machine {
-            button_a: self.button_a,
-            button_b: self.button_b,
+            buttons: self.buttons.clone(),
             prize: self.prize + delta,
         }
     }
 
-    fn num_presses(&self, max_presses: Option<i64>) -> Option<(i64, i64)> {
-        let determinant = self.button_a.x * self.button_b.y - self.button_a.y * self.button_b.x;
-        assert!(determinant != 0);
+    fn num_presses(
+        &self,
+        costs: &[i64],
+        max_presses: Option<i64>,
+    ) -> Result<Option<Vec<i64>>, Error> {
+        if costs.len() != self.buttons.len() {
+            return Err(err_msg(format!(
+                "Machine has {} buttons but {} costs were given",
+                self.buttons.len(),
+                costs.len()
+            )));
+        }
 
-        let a_presses = Ratio::new(
-            self.prize.x * self.button_b.y - self.prize.y * self.button_b.x,
-            determinant,
-        );
-        let b_presses = Ratio::new(
-            self.prize.y * self.button_a.x - self.prize.x * self.button_a.y,
-            determinant,
-        );
+        let buttons: Vec<_> = self
+            .buttons
+            .iter()
+            .zip(costs)
+            .map(|(&delta, &cost)| Button { delta, cost })
+            .collect();
 
-        if is_valid_presses(a_presses, max_presses) && is_valid_presses(b_presses, max_presses) {
-            Some((a_presses.to_integer(), b_presses.to_integer()))
-        } else {
-            None
-        }
+        cheapest_presses(
+            &buttons,
+            self.prize,
+            max_presses.map_or(UNLIMITED, |max| max as i128),
+        )
     }
 
-    fn num_tokens(&self, max_presses: Option<i64>) -> Option<i64> {
-        self.num_presses(max_presses)
-            .map(|(a_presses, b_presses)| a_presses * 3 + b_presses)
+    fn num_tokens(&self, costs: &[i64], max_presses: Option<i64>) -> Result<Option<i64>, Error> {
+        Ok(self.num_presses(costs, max_presses)?.map(|presses| {
+            presses
+                .iter()
+                .zip(costs)
+                .map(|(presses, cost)| presses * cost)
+                .sum()
+        }))
     }
 }
 
-fn get_total_tokens(machines: &[Machine], max_presses: Option<i64>) -> i64 {
+fn get_total_tokens(
+    machines: &[Machine],
+    costs: &[i64],
+    max_presses: Option<i64>,
+) -> Result<i64, Error> {
     machines
         .iter()
-        .filter_map(|machine| machine.num_tokens(max_presses))
+        .map(|machine| machine.num_tokens(costs, max_presses))
+        .filter_map(Result::transpose)
         .sum()
 }
 
@@ -143,7 +318,7 @@ impl super::Solver for Solver {
     }
 
     fn solve(machines: Self::Problem) -> (Option<String>, Option<String>) {
-        let part1 = get_total_tokens(&machines, Some(100));
+        let part1 = get_total_tokens(&machines, &TOKEN_COSTS, Some(100));
         let delta = Position {
             x: 10000000000000,
             y: 10000000000000,
@@ -152,7 +327,89 @@ impl super::Solver for Solver {
             .iter()
             .map(|machine| machine.with_prize_offset(delta))
             .collect();
-        let part2 = get_total_tokens(&updated_machines, None);
-        (Some(part1.to_string()), Some(part2.to_string()))
+        let part2 = get_total_tokens(&updated_machines, &TOKEN_COSTS, None);
+
+        let display = |tokens: Result<i64, Error>| match tokens {
+            Ok(tokens) => Some(tokens.to_string()),
+            Err(err) => {
+                println!("{}", err);
+                None
+            }
+        };
+        (display(part1), display(part2))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{get_total_tokens, parse::parse_input, Machine, TOKEN_COSTS};
+    use crate::common::Position;
+
+    const EXAMPLE: &str = "\
+Button A: X+94, Y+34
+Button B: X+22, Y+67
+Prize: X=8400, Y=5400
+
+Button A: X+26, Y+66
+Button B: X+67, Y+21
+Prize: X=12748, Y=12176
+
+Button A: X+17, Y+86
+Button B: X+84, Y+37
+Prize: X=7870, Y=6450
+
+Button A: X+69, Y+23
+Button B: X+27, Y+71
+Prize: X=18641, Y=10279
+";
+
+    #[test]
+    fn test_total_tokens() {
+        let machines = parse_input(EXAMPLE).unwrap();
+        assert_eq!(
+            get_total_tokens(&machines, &TOKEN_COSTS, Some(100)).unwrap(),
+            480
+        );
+    }
+
+    fn machine(buttons: &[(i64, i64)], prize: (i64, i64)) -> Machine {
+        Machine {
+            buttons: buttons.iter().map(|&(x, y)| Position { x, y }).collect(),
+            prize: Position {
+                x: prize.0,
+                y: prize.1,
+            },
+        }
+    }
+
+    #[test]
+    fn test_collinear_buttons() {
+        let machine = machine(&[(2, 2), (1, 1)], (10, 10));
+        assert_eq!(machine.num_tokens(&[3, 1], None).unwrap(), Some(10));
+        assert_eq!(machine.num_tokens(&[1, 1], None).unwrap(), Some(5));
+
+        let machine = self::machine(&[(4, 6), (6, 9)], (14, 21));
+        assert_eq!(
+            machine.num_presses(&[1, 1], None).unwrap(),
+            Some(vec![2, 1])
+        );
+        assert_eq!(machine.num_presses(&[1, 1], Some(1)).unwrap(), None);
+        assert_eq!(
+            self::machine(&[(2, 2), (1, 1)], (3, 4))
+                .num_tokens(&[3, 1], None)
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_many_buttons() {
+        let machine = machine(&[(3, 0), (0, 3), (1, 1)], (4, 4));
+        assert_eq!(machine.num_tokens(&[3, 1, 1], None).unwrap(), Some(4));
+        assert_eq!(
+            machine.num_presses(&[1, 1, 5], None).unwrap(),
+            Some(vec![1, 1, 1])
+        );
+        assert!(machine.num_tokens(&[1, 1], None).is_err());
     }
 }
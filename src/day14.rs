@@ -1,5 +1,6 @@
-use std::cmp::Ordering;
+use std::{cmp::Ordering, collections::HashSet, path::Path};
 
+use ansi_term::Colour;
 use failure::{err_msg, Error};
 use itertools::Itertools;
 use nom::{
@@ -11,7 +12,13 @@ use nom::{
     IResult,
 };
 
-use crate::{common::Position, parsers::signed};
+use crate::{
+    common::Position,
+    display::{Frame, Player},
+    export::{self, Format},
+    math::crt,
+    parsers::signed,
+};
 
 pub struct Robot {
     position: Position,
@@ -35,23 +42,17 @@ impl Robot {
     }
 }
 
-#[allow(unused)]
-fn display_robots(positions: impl Iterator<Item = Position>, size: Position) {
+fn robots_frame(positions: impl Iterator<Item = Position>, size: Position) -> Frame {
     let counts = positions.map(|pos| (pos, 1)).into_grouping_map().sum();
 
-    for y in 0..size.y {
-        for x in 0..size.x {
-            let pos = Position { x, y };
-            print!(
-                "{}",
-                counts
-                    .get(&pos)
-                    .map(|c| c.to_string())
-                    .unwrap_or_else(|| ".".to_string())
-            );
-        }
-        println!();
-    }
+    let mut frame = Frame::new(size);
+    frame.draw_grid(&counts, |&count: &u32| {
+        (
+            char::from_digit(count, 10).unwrap_or('+'),
+            Colour::Green.bold(),
+        )
+    });
+    frame
 }
 
 fn find_safety_factor(robots: &[Robot], seconds: i64, size: Position) -> usize {
@@ -80,30 +81,218 @@ fn find_safety_factor(robots: &[Robot], seconds: i64, size: Position) -> usize {
     quadrants.values().product()
 }
 
-fn find_tree(robots: &[Robot], size: Position) -> i64 {
-    let num_robots = robots.len() as i64;
+// How far, in standard deviations, the best score must be clear of the next best for a
+// frame to count as the tree.
+const OUTLIER_SIGMAS: f64 = 3.0;
+const ENTROPY_BLOCK_SIZE: i64 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Detector {
+    Variance,
+    Entropy,
+    LargestComponent,
+    LongestRun,
+}
+
+impl Detector {
+    const ALL: [Detector; 4] = [
+        Detector::Variance,
+        Detector::Entropy,
+        Detector::LargestComponent,
+        Detector::LongestRun,
+    ];
+
+    // A score for a frame, higher when the robots look more like a picture.
+    fn score(self, positions: &[Position], size: Position) -> f64 {
+        match self {
+            Detector::Variance => {
+                -(variance(positions.iter().map(|pos| pos.x))
+                    + variance(positions.iter().map(|pos| pos.y)))
+            }
+            Detector::Entropy => -block_entropy(positions),
+            Detector::LargestComponent => largest_component(positions) as f64,
+            Detector::LongestRun => longest_run(positions, size) as f64,
+        }
+    }
+}
+
+fn variance(values: impl Iterator<Item = i64> + Clone) -> f64 {
+    let count = values.clone().count() as f64;
+    let mean = values.clone().sum::<i64>() as f64 / count;
+    values
+        .map(|value| (value as f64 - mean).powi(2))
+        .sum::<f64>()
+        / count
+}
+
+fn block_entropy(positions: &[Position]) -> f64 {
+    let counts = positions
+        .iter()
+        .map(|pos| (pos.x / ENTROPY_BLOCK_SIZE, pos.y / ENTROPY_BLOCK_SIZE))
+        .counts();
+    let total = positions.len() as f64;
+
+    counts
+        .values()
+        .map(|&count| {
+            let p = count as f64 / total;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+fn largest_component(positions: &[Position]) -> usize {
+    let mut unvisited: HashSet<Position> = positions.iter().cloned().collect();
+    let mut largest = 0;
+
+    while let Some(&start) = unvisited.iter().next() {
+        unvisited.remove(&start);
+        let mut to_visit = vec![start];
+        let mut size = 0;
+
+        while let Some(pos) = to_visit.pop() {
+            size += 1;
+            for next in pos.adjacent() {
+                if unvisited.remove(&next) {
+                    to_visit.push(next);
+                }
+            }
+        }
+
+        largest = largest.max(size);
+    }
+
+    largest
+}
+
+fn longest_run(positions: &[Position], size: Position) -> usize {
+    let mut occupied = vec![false; (size.x * size.y) as usize];
+    for pos in positions {
+        occupied[(pos.y * size.x + pos.x) as usize] = true;
+    }
+
+    occupied
+        .chunks(size.x as usize)
+        .flat_map(|row| row.split(|&occupied| !occupied))
+        .map(|run| run.len())
+        .max()
+        .unwrap_or(0)
+}
+
+// The index of the highest score, if it stands well clear of the rest.
+fn find_outlier(scores: &[f64]) -> Option<usize> {
+    let (best, &max) = scores
+        .iter()
+        .enumerate()
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))?;
+
+    let others = scores
+        .iter()
+        .enumerate()
+        .filter(|&(index, _)| index != best)
+        .map(|(_, &score)| score);
+    let count = others.clone().count() as f64;
+    let mean = others.clone().sum::<f64>() / count;
+    let std_dev = (others
+        .clone()
+        .map(|score| (score - mean).powi(2))
+        .sum::<f64>()
+        / count)
+        .sqrt();
+    let runner_up = others.max_by(|a, b| a.total_cmp(b))?;
+
+    (max - runner_up > OUTLIER_SIGMAS * std_dev).then_some(best)
+}
 
-    for secs in 0..1000000 {
-        let moved: Vec<_> = robots
-            .iter()
-            .map(|robot| robot.position_after(secs, size))
+// Each axis repeats independently, so find when the robots bunch up along each one and
+// combine the two.
+fn find_tree_by_variance(robots: &[Robot], size: Position) -> Result<i64, Error> {
+    let axis_time = |period: i64, axis: fn(Position) -> i64| {
+        let scores: Vec<_> = (0..period)
+            .map(|secs| {
+                -variance(
+                    robots
+                        .iter()
+                        .map(|robot| axis(robot.position_after(secs, size))),
+                )
+            })
             .collect();
+        find_outlier(&scores).map(|secs| secs as i64)
+    };
 
-        let x_mean: i64 = moved.iter().map(|pos| pos.x).sum::<i64>() / num_robots;
-        let x_variance = moved.iter().map(|pos| (pos.x - x_mean).pow(2)).sum::<i64>() / num_robots;
+    let x_secs =
+        axis_time(size.x, |pos| pos.x).ok_or_else(|| err_msg("No time with low variance in x"))?;
+    let y_secs =
+        axis_time(size.y, |pos| pos.y).ok_or_else(|| err_msg("No time with low variance in y"))?;
+
+    crt(&[(x_secs, size.x), (y_secs, size.y)])
+        .map(|(secs, _)| secs)
+        .ok_or_else(|| {
+            err_msg(format!(
+                "No time is {} mod {} and {} mod {}",
+                x_secs, size.x, y_secs, size.y
+            ))
+        })
+}
 
-        let y_mean: i64 = moved.iter().map(|pos| pos.y).sum::<i64>() / num_robots;
-        let y_variance = moved.iter().map(|pos| (pos.y - y_mean).pow(2)).sum::<i64>() / num_robots;
+fn find_tree_with(robots: &[Robot], size: Position, detector: Detector) -> Result<i64, Error> {
+    if detector == Detector::Variance {
+        return find_tree_by_variance(robots, size);
+    }
+
+    let scores: Vec<_> = (0..size.x * size.y)
+        .map(|secs| {
+            let moved: Vec<_> = robots
+                .iter()
+                .map(|robot| robot.position_after(secs, size))
+                .collect();
+            detector.score(&moved, size)
+        })
+        .collect();
+
+    find_outlier(&scores)
+        .map(|secs| secs as i64)
+        .ok_or_else(|| err_msg(format!("{:?} detector found no tree", detector)))
+}
 
-        if x_variance < 500 && y_variance < 500 {
-            println!("After {}s: ({}, {})", secs, x_variance, y_variance);
-            display_robots(moved.iter().cloned(), size);
-            println!();
-            return secs;
+fn find_tree(robots: &[Robot], size: Position) -> Result<i64, Error> {
+    let mut errors = vec![];
+
+    for detector in Detector::ALL {
+        match find_tree_with(robots, size, detector) {
+            Ok(secs) => return Ok(secs),
+            Err(err) => errors.push(err.to_string()),
         }
     }
 
-    0
+    Err(err_msg(errors.join("; ")))
+}
+
+fn motion_frames(robots: &[Robot], size: Position) -> impl Iterator<Item = Frame> + '_ {
+    (0..).map(move |secs| {
+        robots_frame(
+            robots.iter().map(|robot| robot.position_after(secs, size)),
+            size,
+        )
+        .caption(format!("After {}s", secs))
+    })
+}
+
+pub fn visualize_input(data: String) -> Result<(), Error> {
+    let robots = <Solver as super::Solver>::parse_input(data)?;
+    Player::new(10).play(motion_frames(&robots, Position { x: 101, y: 103 }))
+}
+
+pub fn export_input(data: String, path: &Path, max_frames: usize) -> Result<(), Error> {
+    let robots = <Solver as super::Solver>::parse_input(data)?;
+    let size = Position { x: 101, y: 103 };
+
+    if Format::from_path(path)?.is_animated() {
+        export::save(path, motion_frames(&robots, size), max_frames)
+    } else {
+        let secs = find_tree(&robots, size)?;
+        export::save(path, motion_frames(&robots, size).nth(secs as usize), 1)
+    }
 }
 
 pub struct Solver {}
@@ -134,16 +323,88 @@ impl super::Solver for Solver {
     fn solve(robots: Self::Problem) -> (Option<String>, Option<String>) {
         let size = Position { x: 101, y: 103 };
         let part1 = find_safety_factor(&robots, 100, size);
-        let part2 = find_tree(&robots, size);
-        (Some(part1.to_string()), Some(part2.to_string()))
+        let part2 = match find_tree(&robots, size) {
+            Ok(secs) => {
+                let moved = robots.iter().map(|robot| robot.position_after(secs, size));
+                println!(
+                    "{}",
+                    robots_frame(moved, size).caption(format!("After {}s", secs))
+                );
+                Some(secs.to_string())
+            }
+            Err(err) => {
+                println!("{}", err);
+                None
+            }
+        };
+        (Some(part1.to_string()), part2)
     }
 }
 
 #[cfg(test)]
 mod test {
-    use super::Robot;
+    use super::{find_tree, find_tree_with, Detector, Robot};
     use crate::common::Position;
 
+    const SIZE: Position = Position { x: 41, y: 43 };
+
+    // Robots at random positions with random velocities, plus a filled triangle of robots
+    // that lines up at the given time.
+    fn robots_with_tree(tree_secs: Option<i64>) -> Vec<Robot> {
+        let mut seed = 12345u64;
+        let mut random = |max: i64| {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            ((seed >> 33) % max as u64) as i64
+        };
+
+        let mut positions = vec![];
+        if tree_secs.is_some() {
+            for row in 0..12 {
+                for x in 20 - row..=20 + row {
+                    positions.push(Position { x, y: 15 + row });
+                }
+            }
+        }
+        while positions.len() < 200 {
+            positions.push(Position {
+                x: random(SIZE.x),
+                y: random(SIZE.y),
+            });
+        }
+
+        let secs = tree_secs.unwrap_or(0);
+        positions
+            .into_iter()
+            .map(|position| {
+                let velocity = Position {
+                    x: random(SIZE.x),
+                    y: random(SIZE.y),
+                };
+                Robot {
+                    position: Robot { position, velocity }.position_after(-secs, SIZE),
+                    velocity,
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_find_tree() {
+        let robots = robots_with_tree(Some(321));
+        for detector in Detector::ALL {
+            assert_eq!(
+                find_tree_with(&robots, SIZE, detector).unwrap(),
+                321,
+                "{:?}",
+                detector
+            );
+        }
+
+        assert!(find_tree(&robots_with_tree(None), SIZE).is_err());
+    }
+
     #[test]
     fn test_robot() {
         let robot = Robot {
@@ -1,194 +1,338 @@
-use std::collections::HashMap;
-
-use crate::common::{Direction, Position};
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+
+use crate::{
+    common::{Direction, Position},
+    display::{Frame, Player},
+    export,
+};
+use ansi_term::Colour;
 use failure::{err_msg, Error};
+use itertools::iproduct;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum Side {
-    Left,
-    Right,
+pub struct Crate {
+    corner: Position,
+    width: i64,
+    height: i64,
 }
 
-impl Side {
-    fn other(self) -> Self {
-        match self {
-            Side::Left => Side::Right,
-            Side::Right => Side::Left,
-        }
+impl Crate {
+    fn tiles(self) -> impl Iterator<Item = Position> {
+        iproduct!(0..self.height, 0..self.width).map(move |(dy, dx)| Position {
+            x: self.corner.x + dx,
+            y: self.corner.y + dy,
+        })
     }
 
-    fn as_direction(self) -> Direction {
-        match self {
-            Side::Left => Direction::West,
-            Side::Right => Direction::East,
+    fn symbol_at(self, position: Position) -> char {
+        if self.width == 1 {
+            'O'
+        } else if position.x == self.corner.x {
+            '['
+        } else if position.x == self.corner.x + self.width - 1 {
+            ']'
+        } else {
+            '='
         }
     }
+
+    fn gps_coordinate(self) -> i64 {
+        100 * self.corner.y + self.corner.x
+    }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Copy)]
-pub enum Object {
-    Wall,
-    SmallBox,
-    LargeBox(Side),
+// Enough to redo or undo a move, even one that was blocked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MoveRecord {
+    direction: Direction,
+    moved: bool,
+    crates: Vec<usize>,
 }
 
-#[allow(unused)]
-fn display_map(robot_position: Position, objects: &HashMap<Position, Object>) {
-    let max_x = objects.keys().map(|pos| pos.x).max().unwrap();
-    let max_y = objects.keys().map(|pos| pos.y).max().unwrap();
-
-    for y in 0..=max_y {
-        for x in 0..=max_x {
-            let pos = Position { x, y };
-
-            print!(
-                "{}",
-                if pos == robot_position {
-                    "@"
-                } else {
-                    match objects.get(&pos) {
-                        None => ".",
-                        Some(Object::SmallBox) => "O",
-                        Some(Object::LargeBox(Side::Left)) => "[",
-                        Some(Object::LargeBox(Side::Right)) => "]",
-                        Some(Object::Wall) => "#",
-                    }
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Warehouse {
+    robot: Position,
+    walls: HashSet<Position>,
+    crates: Vec<Crate>,
+    tiles: HashMap<Position, usize>,
+}
+
+impl Warehouse {
+    fn new(robot: Position, walls: HashSet<Position>, crates: Vec<Crate>) -> Result<Self, Error> {
+        let mut tiles = HashMap::new();
+        for (index, &crate_) in crates.iter().enumerate() {
+            for tile in crate_.tiles() {
+                if walls.contains(&tile) || tiles.insert(tile, index).is_some() {
+                    return Err(err_msg(format!("Crates overlap at {}", tile)));
                 }
-            );
+            }
         }
-        println!();
+
+        Ok(Warehouse {
+            robot,
+            walls,
+            crates,
+            tiles,
+        })
     }
 
-    println!();
-}
+    // Scales every tile up to a block of width by height tiles.
+    fn expand(&self, width: i64, height: i64) -> Self {
+        let scale = |pos: Position| Position {
+            x: pos.x * width,
+            y: pos.y * height,
+        };
 
-fn find_moved_objects(
-    robot_position: Position,
-    objects: &HashMap<Position, Object>,
-    direction: Direction,
-) -> Option<HashMap<Position, Object>> {
-    let mut to_check = vec![robot_position.step(direction)];
-    let mut moved_objects = HashMap::new();
+        let walls = self
+            .walls
+            .iter()
+            .flat_map(|&wall| {
+                Crate {
+                    corner: scale(wall),
+                    width,
+                    height,
+                }
+                .tiles()
+            })
+            .collect();
 
-    while let Some(pos) = to_check.pop() {
-        if moved_objects.contains_key(&pos) {
-            continue;
-        }
+        let crates = self
+            .crates
+            .iter()
+            .map(|crate_| Crate {
+                corner: scale(crate_.corner),
+                width: crate_.width * width,
+                height: crate_.height * height,
+            })
+            .collect();
 
-        match objects.get(&pos) {
-            None => {}
-            Some(Object::Wall) => {
+        Warehouse::new(scale(self.robot), walls, crates).unwrap()
+    }
+
+    fn find_moved_crates(&self, direction: Direction) -> Option<Vec<usize>> {
+        let mut to_check = vec![self.robot.step(direction)];
+        let mut moved = vec![];
+
+        while let Some(pos) = to_check.pop() {
+            if self.walls.contains(&pos) {
                 return None;
             }
-            Some(Object::SmallBox) => {
-                moved_objects.insert(pos, Object::SmallBox);
-                to_check.push(pos.step(direction));
+
+            if let Some(&index) = self.tiles.get(&pos) {
+                if !moved.contains(&index) {
+                    moved.push(index);
+                    to_check.extend(self.crates[index].tiles().map(|tile| tile.step(direction)));
+                }
+            }
+        }
+
+        Some(moved)
+    }
+
+    fn shift(&mut self, crates: &[usize], direction: Direction) {
+        for &index in crates {
+            for tile in self.crates[index].tiles() {
+                self.tiles.remove(&tile);
+            }
+        }
+
+        for &index in crates {
+            let crate_ = &mut self.crates[index];
+            crate_.corner = crate_.corner.step(direction);
+            for tile in crate_.tiles() {
+                self.tiles.insert(tile, index);
             }
-            Some(&Object::LargeBox(side)) => {
-                moved_objects.insert(pos, Object::LargeBox(side));
-                to_check.push(pos.step(direction));
-
-                let other_side = side.other();
-                let other_pos = pos.step(other_side.as_direction());
-                moved_objects.insert(other_pos, Object::LargeBox(other_side));
-                to_check.push(other_pos.step(direction));
+        }
+
+        self.robot = self.robot.step(direction);
+    }
+
+    fn perform_move(&mut self, direction: Direction) -> MoveRecord {
+        match self.find_moved_crates(direction) {
+            Some(crates) => {
+                self.shift(&crates, direction);
+                MoveRecord {
+                    direction,
+                    moved: true,
+                    crates,
+                }
             }
+            None => MoveRecord {
+                direction,
+                moved: false,
+                crates: vec![],
+            },
         }
     }
 
-    Some(moved_objects)
-}
+    fn perform_moves(&mut self, moves: &[Direction]) -> Vec<MoveRecord> {
+        moves
+            .iter()
+            .map(|&direction| self.perform_move(direction))
+            .collect()
+    }
 
-fn perform_move(
-    robot_position: &mut Position,
-    objects: &mut HashMap<Position, Object>,
-    direction: Direction,
-) {
-    if let Some(to_move) = find_moved_objects(*robot_position, objects, direction) {
-        for (pos, _) in to_move.iter() {
-            objects.remove(pos);
+    fn undo(&mut self, record: &MoveRecord) {
+        if record.moved {
+            self.shift(&record.crates, record.direction.reverse());
         }
+    }
 
-        for (pos, object) in to_move {
-            objects.insert(pos.step(direction), object);
+    // Applies a recorded move, checking that it's still the move the robot would make.
+    fn replay(&mut self, record: &MoveRecord) -> Result<(), Error> {
+        let robot = self.robot;
+        let replayed = self.perform_move(record.direction);
+        if replayed != *record {
+            return Err(err_msg(format!(
+                "Move {} from {} doesn't match the log: {:?} vs {:?}",
+                record.direction.as_char(),
+                robot,
+                replayed,
+                record
+            )));
         }
+        Ok(())
+    }
 
-        *robot_position = robot_position.step(direction);
+    fn gps_sum(&self) -> i64 {
+        self.crates
+            .iter()
+            .map(|crate_| crate_.gps_coordinate())
+            .sum()
     }
+
+    fn frame(&self) -> Frame {
+        let mut frame = Frame::enclosing(&self.walls);
+
+        frame.draw(&self.walls, '#', Colour::Red.dimmed());
+        for (&tile, &index) in self.tiles.iter() {
+            frame.set(
+                tile,
+                self.crates[index].symbol_at(tile),
+                Colour::Yellow.bold(),
+            );
+        }
+        frame.set(self.robot, '@', Colour::Blue.bold());
+
+        frame
+    }
+}
+
+fn find_box_location_sum(mut warehouse: Warehouse, moves: &[Direction]) -> (i64, Vec<MoveRecord>) {
+    let log = warehouse.perform_moves(moves);
+    (warehouse.gps_sum(), log)
 }
 
-fn perform_moves(
-    robot_position: &mut Position,
-    objects: &mut HashMap<Position, Object>,
-    moves: &[Direction],
-) {
-    for &next_move in moves {
-        perform_move(robot_position, objects, next_move);
+// Replays a move log from the starting state, checking it ends with the expected GPS
+// sum, and that undoing it gets back to the start.
+fn check_move_log(start: &Warehouse, log: &[MoveRecord], gps_sum: i64) -> Result<(), Error> {
+    let mut warehouse = start.clone();
+    for record in log {
+        warehouse.replay(record)?;
     }
+
+    if warehouse.gps_sum() != gps_sum {
+        return Err(err_msg(format!(
+            "Replaying the moves gives a GPS sum of {} rather than {}",
+            warehouse.gps_sum(),
+            gps_sum
+        )));
+    }
+
+    for record in log.iter().rev() {
+        warehouse.undo(record);
+    }
+
+    if warehouse != *start {
+        return Err(err_msg("Undoing the moves doesn't restore the warehouse"));
+    }
+
+    Ok(())
 }
 
-fn find_box_location_sum(
-    mut robot_position: Position,
-    mut objects: HashMap<Position, Object>,
-    moves: &[Direction],
-) -> i64 {
-    perform_moves(&mut robot_position, &mut objects, moves);
-    objects
-        .iter()
-        .filter_map(|(&position, &object)| {
-            if matches!(object, Object::SmallBox | Object::LargeBox(Side::Left)) {
-                Some(position)
-            } else {
-                None
-            }
-        })
-        .map(|pos| 100 * pos.y + pos.x)
-        .sum()
+fn move_frames(mut warehouse: Warehouse, moves: &[Direction]) -> impl Iterator<Item = Frame> + '_ {
+    [warehouse.frame().caption("Initial state")]
+        .into_iter()
+        .chain(moves.iter().enumerate().map(move |(index, &direction)| {
+            warehouse.perform_move(direction);
+            warehouse.frame().caption(format!(
+                "Move {}/{}: {}",
+                index + 1,
+                moves.len(),
+                direction.as_char()
+            ))
+        }))
+}
+
+pub fn visualize_input(data: String) -> Result<(), Error> {
+    let (warehouse, moves) = <Solver as super::Solver>::parse_input(data)?;
+    Player::new(20).play(move_frames(warehouse.expand(2, 1), &moves))
 }
 
-fn expand_map(
-    robot_position: Position,
-    objects: &HashMap<Position, Object>,
-) -> (Position, HashMap<Position, Object>) {
-    let new_robot_position = Position {
-        x: robot_position.x * 2,
-        y: robot_position.y,
-    };
-
-    let new_objects = objects
-        .iter()
-        .flat_map(|(pos, obj)| {
-            let new_pos_left = Position {
-                x: pos.x * 2,
-                y: pos.y,
-            };
-            let new_pos_right = Position {
-                x: pos.x * 2 + 1,
-                y: pos.y,
-            };
-
-            [new_pos_left, new_pos_right].into_iter().zip(match obj {
-                Object::Wall => [Object::Wall, Object::Wall],
-                Object::SmallBox => [Object::LargeBox(Side::Left), Object::LargeBox(Side::Right)],
-                Object::LargeBox(_) => {
-                    panic!("Can't expand large box");
+pub fn export_input(data: String, path: &Path, max_frames: usize) -> Result<(), Error> {
+    let (warehouse, moves) = <Solver as super::Solver>::parse_input(data)?;
+    export::save(
+        path,
+        move_frames(warehouse.expand(2, 1), &moves),
+        max_frames,
+    )
+}
+
+fn parse_crates(lines: &[&str]) -> Result<Vec<Crate>, Error> {
+    let mut crates = vec![];
+
+    for (y, line) in lines.iter().enumerate() {
+        let mut start = None;
+        for (x, c) in line.char_indices() {
+            let corner = Position::from((x, y));
+            match (c, start) {
+                ('O', None) => crates.push(Crate {
+                    corner,
+                    width: 1,
+                    height: 1,
+                }),
+                ('[', None) => start = Some(corner),
+                ('=', Some(_)) => {}
+                (']', Some(corner)) => {
+                    crates.push(Crate {
+                        corner,
+                        width: x as i64 - corner.x + 1,
+                        height: 1,
+                    });
+                    start = None;
                 }
-            })
-        })
-        .collect();
+                ('O' | '[' | '=' | ']', _) => {
+                    return Err(err_msg(format!("Unexpected {} at {}", c, corner)))
+                }
+                _ => {}
+            }
+        }
 
-    (new_robot_position, new_objects)
+        if let Some(corner) = start {
+            return Err(err_msg(format!("Unclosed crate at {}", corner)));
+        }
+    }
+
+    Ok(crates)
 }
 
 pub struct Solver {}
 
 impl super::Solver for Solver {
-    type Problem = (Position, HashMap<Position, Object>, Box<[Direction]>);
+    type Problem = (Warehouse, Box<[Direction]>);
 
     fn parse_input(data: String) -> Result<Self::Problem, Error> {
         let lines = data.lines().collect::<Vec<_>>();
+        let map: Vec<_> = lines
+            .iter()
+            .take_while(|line| !line.is_empty())
+            .cloned()
+            .collect();
 
-        let start = lines
+        let robot = map
             .iter()
             .enumerate()
             .find_map(|(y, line)| {
@@ -197,19 +341,18 @@ impl super::Solver for Solver {
             })
             .ok_or(err_msg("Failed to find start position"))?;
 
-        let objects = lines
+        let walls = map
             .iter()
-            .take_while(|line| !line.is_empty())
             .enumerate()
             .flat_map(|(y, line)| {
-                line.char_indices().filter_map(move |(x, c)| match c {
-                    '#' => Some(((x, y).into(), Object::Wall)),
-                    'O' => Some(((x, y).into(), Object::SmallBox)),
-                    _ => None,
-                })
+                line.char_indices()
+                    .filter(|&(_, c)| c == '#')
+                    .map(move |(x, _)| (x, y).into())
             })
             .collect();
 
+        let warehouse = Warehouse::new(robot, walls, parse_crates(&map)?)?;
+
         let moves = lines
             .iter()
             .skip_while(|line| !line.is_empty())
@@ -219,15 +362,77 @@ impl super::Solver for Solver {
             .collect::<Result<Vec<_>, _>>()?
             .into_boxed_slice();
 
-        Ok((start, objects, moves))
+        Ok((warehouse, moves))
     }
 
-    fn solve((robot_position, objects, moves): Self::Problem) -> (Option<String>, Option<String>) {
-        let part1 = find_box_location_sum(robot_position, objects.clone(), &moves);
+    fn solve((warehouse, moves): Self::Problem) -> (Option<String>, Option<String>) {
+        let (part1, log) = find_box_location_sum(warehouse.clone(), &moves);
+        check_move_log(&warehouse, &log, part1).unwrap();
 
-        let (new_robot_position, expanded_objects) = expand_map(robot_position, &objects);
-        let part2 = find_box_location_sum(new_robot_position, expanded_objects, &moves);
+        let expanded = warehouse.expand(2, 1);
+        let (part2, log) = find_box_location_sum(expanded.clone(), &moves);
+        check_move_log(&expanded, &log, part2).unwrap();
 
         (Some(part1.to_string()), Some(part2.to_string()))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{check_move_log, find_box_location_sum, Solver};
+    use crate::{common::Direction, Solver as _};
+
+    const EXAMPLE: &str = "\
+########
+#..O.O.#
+##@.O..#
+#...O..#
+#.#.O..#
+#...O..#
+#......#
+########
+
+<^^>>>vv<v>>v<<
+";
+
+    #[test]
+    fn test_moves() {
+        let (warehouse, moves) = Solver::parse_input(EXAMPLE.to_string()).unwrap();
+
+        let (gps_sum, log) = find_box_location_sum(warehouse.clone(), &moves);
+        assert_eq!(gps_sum, 2028);
+        check_move_log(&warehouse, &log, gps_sum).unwrap();
+        assert!(check_move_log(&warehouse, &[&log[..1], &log[2..]].concat(), gps_sum).is_err());
+
+        let expanded = warehouse.expand(2, 1);
+        let (gps_sum, log) = find_box_location_sum(expanded.clone(), &moves);
+        check_move_log(&expanded, &log, gps_sum).unwrap();
+    }
+
+    #[test]
+    fn test_large_crates() {
+        let (warehouse, _) =
+            Solver::parse_input("#######\n#.....#\n#.[=].#\n#..@..#\n#######\n\n".to_string())
+                .unwrap();
+        let start = warehouse.expand(1, 2);
+        let mut warehouse = start.clone();
+
+        let record = warehouse.perform_move(Direction::North);
+        assert!(record.moved);
+        assert_eq!(record.crates, vec![0]);
+        assert_eq!(warehouse.gps_sum(), 100 * 3 + 2);
+
+        let log = [
+            record,
+            warehouse.perform_move(Direction::North),
+            warehouse.perform_move(Direction::North),
+        ];
+        assert!(!log[2].moved);
+        assert_eq!(warehouse.gps_sum(), 100 * 2 + 2);
+
+        for record in log.iter().rev() {
+            warehouse.undo(record);
+        }
+        assert_eq!(warehouse, start);
+    }
+}
@@ -1,15 +1,12 @@
 use crate::{
     a_star,
     common::{find_all_symbols_in_grid, find_symbol_in_grid, Direction, Position},
-    djikstra,
+    display::Frame,
+    djikstra, export,
 };
 use ansi_term::Colour;
 use failure::{err_msg, Error};
-use std::{
-    collections::{HashMap, HashSet},
-    fmt::Debug,
-    hash::Hash,
-};
+use std::{collections::HashSet, fmt::Debug, hash::Hash, path::Path};
 
 #[derive(Clone)]
 struct State<'a> {
@@ -115,42 +112,29 @@ impl djikstra::State for State<'_> {
     }
 }
 
-fn display_route(
+fn route_frame(
     start: Position,
     end: Position,
     walls: &HashSet<Position>,
     route: &[(Position, Direction)],
-) {
-    let route_tiles: HashMap<_, _> = route.iter().cloned().collect();
-
-    let max_x = walls.iter().map(|pos| pos.x).max().unwrap();
-    let max_y = walls.iter().map(|pos| pos.y).max().unwrap();
-
-    for y in 0..=max_y {
-        print!("{:03}: ", y);
-        for x in 0..=max_x {
-            let pos = Position { x, y };
-
-            let (colour, symbol) = if pos == start {
-                (Colour::Blue.bold(), 'S')
-            } else if pos == end {
-                (Colour::Blue.bold(), 'E')
-            } else if let Some(dir) = route_tiles.get(&pos) {
-                (Colour::Green.bold(), dir.as_char())
-            } else if walls.contains(&pos) {
-                (Colour::Red.dimmed(), '#')
-            } else {
-                (Colour::White.dimmed(), '.')
-            };
-
-            print!("{}", colour.paint(symbol.to_string()));
-        }
+) -> Frame {
+    let mut frame = Frame::enclosing(walls);
 
-        println!();
+    frame.draw(walls, '#', Colour::Red.dimmed());
+    for &(position, direction) in route {
+        frame.set(position, direction.as_char(), Colour::Green.bold());
     }
+    frame.set(start, 'S', Colour::Blue.bold());
+    frame.set(end, 'E', Colour::Blue.bold());
+
+    frame
 }
 
-fn find_min_score(start: Position, end: Position, walls: &HashSet<Position>) -> u64 {
+fn find_best_route(
+    start: Position,
+    end: Position,
+    walls: &HashSet<Position>,
+) -> (u64, Vec<(Position, Direction)>) {
     let solution = a_star::solve([State {
         position: start,
         end,
@@ -160,15 +144,21 @@ fn find_min_score(start: Position, end: Position, walls: &HashSet<Position>) ->
     }])
     .unwrap();
 
-    let route: Vec<(Position, Direction)> = solution
+    let route = solution
         .route
         .iter()
         .map(|state| (state.position, state.direction))
         .collect();
 
-    display_route(start, end, walls, &route);
+    (solution.cost, route)
+}
+
+fn find_min_score(start: Position, end: Position, walls: &HashSet<Position>) -> u64 {
+    let (cost, route) = find_best_route(start, end, walls);
 
-    solution.cost
+    print!("{}", route_frame(start, end, walls, &route));
+
+    cost
 }
 
 fn find_tiles_on_best_route(start: Position, end: Position, walls: &HashSet<Position>) -> usize {
@@ -203,6 +193,13 @@ fn find_tiles_on_best_route(start: Position, end: Position, walls: &HashSet<Posi
     tiles.len()
 }
 
+pub fn export_input(data: String, path: &Path, max_frames: usize) -> Result<(), Error> {
+    let (start, end, walls) = <Solver as super::Solver>::parse_input(data)?;
+    let (cost, route) = find_best_route(start, end, &walls);
+    let frame = route_frame(start, end, &walls, &route).caption(format!("Score {}", cost));
+    export::save(path, [frame], max_frames)
+}
+
 pub struct Solver {}
 
 impl super::Solver for Solver {
@@ -1,6 +1,11 @@
-use failure::Error;
+use failure::{err_msg, Error};
 use parse::parse_input;
 use std::fmt::Display;
+use std::str::FromStr;
+
+mod analysis;
+mod asm;
+mod debugger;
 
 mod parse {
     use crate::parsers::unsigned;
@@ -33,6 +38,17 @@ mod parse {
         )(input)
     }
 
+    pub(super) fn parse_program(input: &str) -> Result<Box<[u8]>, Error> {
+        let (_, program) = all_consuming(separated_list1(tag(","), unsigned::<u8>))(input)
+            .map_err(|err| err_msg(format!("Failed to parse program: {}", err)))?;
+
+        if let Some(value) = program.iter().find(|&&value| value > 7) {
+            return Err(err_msg(format!("Invalid 3-bit value {}", value)));
+        }
+
+        Ok(program.into_boxed_slice())
+    }
+
     pub(super) fn parse_input(input: &str) -> Result<(Registers, Box<[u8]>), Error> {
         all_consuming(separated_pair(registers, newline, instructions))(input)
             .map(|(_, val)| val)
@@ -40,7 +56,7 @@ mod parse {
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 enum Register {
     A,
     B,
@@ -57,6 +73,19 @@ impl Register {
     }
 }
 
+impl FromStr for Register {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "A" | "a" => Ok(Register::A),
+            "B" | "b" => Ok(Register::B),
+            "C" | "c" => Ok(Register::C),
+            _ => Err(err_msg(format!("Invalid register {}", s))),
+        }
+    }
+}
+
 impl Display for Register {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -67,23 +96,24 @@ impl Display for Register {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy)]
 enum Operand {
     Literal(u8),
     Register(Register),
 }
 
 impl Operand {
-    fn combo(val: u8) -> Self {
+    fn try_combo(val: u8) -> Option<Self> {
         match val {
-            0..=3 => Operand::Literal(val),
-            4 => Operand::Register(Register::A),
-            5 => Operand::Register(Register::B),
-            6 => Operand::Register(Register::C),
-            _ => panic!("Invalid combo operand {}", val),
+            0..=3 => Some(Operand::Literal(val)),
+            4 => Some(Operand::Register(Register::A)),
+            5 => Some(Operand::Register(Register::B)),
+            6 => Some(Operand::Register(Register::C)),
+            _ => None,
         }
     }
 
+    #[cfg(test)]
     fn evaluate(&self, registers: &Registers) -> u64 {
         match self {
             Operand::Literal(val) => *val as u64,
@@ -107,7 +137,7 @@ impl From<Register> for Operand {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy)]
 enum Instruction {
     Divide(Operand, Operand, Register),
     Xor(Operand, Operand, Register),
@@ -117,6 +147,35 @@ enum Instruction {
 }
 
 impl Instruction {
+    fn try_decode(operator: u8, operand: u8) -> Option<Self> {
+        use Instruction::*;
+        let instruction = match operator {
+            0 => Divide(
+                Register::A.into(),
+                Operand::try_combo(operand)?,
+                Register::A,
+            ),
+            1 => Xor(Register::B.into(), Operand::Literal(operand), Register::B),
+            2 => Mod(Operand::try_combo(operand)?, Register::B),
+            3 => JumpNotZero(Operand::Literal(operand)),
+            4 => Xor(Register::B.into(), Register::C.into(), Register::B),
+            5 => Out(Operand::try_combo(operand)?),
+            6 => Divide(
+                Register::A.into(),
+                Operand::try_combo(operand)?,
+                Register::B,
+            ),
+            7 => Divide(
+                Register::A.into(),
+                Operand::try_combo(operand)?,
+                Register::C,
+            ),
+            _ => return None,
+        };
+        Some(instruction)
+    }
+
+    #[cfg(test)]
     fn execute(&self, registers: &mut Registers) -> (Option<usize>, Option<u8>) {
         use Instruction::*;
         match self {
@@ -178,6 +237,7 @@ impl Display for Instruction {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Registers {
     values: [u64; 3],
 }
@@ -206,127 +266,272 @@ impl Display for Registers {
     }
 }
 
+const STEP_LIMIT: usize = 1000000;
+
+#[derive(Clone, Copy)]
+enum Source {
+    Literal(u64),
+    Register(usize),
+}
+
+impl Source {
+    fn combo(val: u8) -> Option<Self> {
+        Operand::try_combo(val).map(|operand| match operand {
+            Operand::Literal(val) => Source::Literal(val as u64),
+            Operand::Register(reg) => Source::Register(reg.index()),
+        })
+    }
+
+    fn evaluate(self, values: &[u64; 3]) -> u64 {
+        match self {
+            Source::Literal(val) => val,
+            Source::Register(index) => values[index],
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Op {
+    Divide(Source, usize),
+    Xor(Source),
+    Mod(Source),
+    JumpNotZero(usize),
+    Out(Source),
+    Invalid,
+}
+
+impl Op {
+    fn compile(operator: u8, operand: u8) -> Self {
+        let op = match operator {
+            0 => Source::combo(operand).map(|source| Op::Divide(source, 0)),
+            1 => Some(Op::Xor(Source::Literal(operand as u64))),
+            2 => Source::combo(operand).map(Op::Mod),
+            3 => Some(Op::JumpNotZero(operand as usize)),
+            4 => Some(Op::Xor(Source::Register(2))),
+            5 => Source::combo(operand).map(Op::Out),
+            6 => Source::combo(operand).map(|source| Op::Divide(source, 1)),
+            7 => Source::combo(operand).map(|source| Op::Divide(source, 2)),
+            _ => None,
+        };
+        op.unwrap_or(Op::Invalid)
+    }
+}
+
+struct Program {
+    code: Box<[u8]>,
+    ops: Box<[Op]>,
+}
+
+impl Program {
+    fn new(code: Box<[u8]>) -> Self {
+        let ops = code
+            .windows(2)
+            .map(|pair| Op::compile(pair[0], pair[1]))
+            .collect();
+        Program { code, ops }
+    }
+}
+
+enum Step {
+    Halted,
+    Executed(Option<u8>),
+}
+
 struct Computer<'a> {
     registers: Registers,
-    program: &'a [u8],
+    program: &'a Program,
     instruction_pointer: usize,
     output: Vec<u8>,
     expected_output: Option<&'a [u8]>,
-    debug: bool,
+    steps: usize,
+    step_limit: Option<usize>,
 }
 
 impl<'a> Computer<'a> {
-    fn new(registers: Registers, program: &'a [u8], expected_output: Option<&'a [u8]>) -> Self {
+    fn new(registers: Registers, program: &'a Program, expected_output: Option<&'a [u8]>) -> Self {
         Computer {
             registers,
             program,
             instruction_pointer: 0,
             output: vec![],
             expected_output,
-            debug: false,
+            steps: 0,
+            step_limit: Some(STEP_LIMIT),
         }
     }
 
-    fn parse_instruction(&self, operator: u8, operand: u8) -> Instruction {
-        use Instruction::*;
-        match operator {
-            0 => Divide(Register::A.into(), Operand::combo(operand), Register::A),
-            1 => Xor(Register::B.into(), Operand::Literal(operand), Register::B),
-            2 => Mod(Operand::combo(operand), Register::B),
-            3 => JumpNotZero(Operand::Literal(operand)),
-            4 => Xor(Register::B.into(), Register::C.into(), Register::B),
-            5 => Out(Operand::combo(operand)),
-            6 => Divide(Register::A.into(), Operand::combo(operand), Register::B),
-            7 => Divide(Register::A.into(), Operand::combo(operand), Register::C),
-            _ => panic!("Invalid operator {}", operator),
+    fn next_instruction(&self) -> Result<Option<Instruction>, Error> {
+        match self
+            .program
+            .code
+            .get(self.instruction_pointer..self.instruction_pointer + 2)
+        {
+            Some(&[operator, operand]) => Instruction::try_decode(operator, operand)
+                .map(Some)
+                .ok_or_else(|| self.invalid_instruction()),
+            _ => Ok(None),
         }
     }
 
-    fn next_instruction(&self) -> Option<Instruction> {
-        if self.instruction_pointer < self.program.len() {
-            let operator = self.program[self.instruction_pointer];
-            let operand = self.program[self.instruction_pointer + 1];
-
-            Some(self.parse_instruction(operator, operand))
-        } else {
-            None
-        }
+    fn invalid_instruction(&self) -> Error {
+        err_msg(format!(
+            "Invalid instruction at {:02}",
+            self.instruction_pointer
+        ))
     }
 
-    fn run(&mut self) -> bool {
-        if self.debug {
-            println!("Program: {:?}", self.program);
+    fn step(&mut self) -> Result<Step, Error> {
+        let Some(&op) = self.program.ops.get(self.instruction_pointer) else {
+            return Ok(Step::Halted);
+        };
+
+        if self.step_limit.is_some_and(|limit| self.steps >= limit) {
+            return Err(err_msg(format!(
+                "Program didn't halt within {} steps",
+                self.steps
+            )));
         }
-        while let Some(instruction) = self.next_instruction() {
-            if self.debug {
-                println!(
-                    "{:02} {} {}",
-                    self.instruction_pointer, instruction, self.registers
-                );
-            }
-            let (next, output) = instruction.execute(&mut self.registers);
 
-            if let Some(instruction_pointer) = next {
-                self.instruction_pointer = instruction_pointer;
-            } else {
-                self.instruction_pointer += 2;
+        let values = &mut self.registers.values;
+        let mut output = None;
+        self.instruction_pointer = match op {
+            Op::Divide(source, store) => {
+                values[store] = values[0]
+                    .checked_shr(source.evaluate(values).try_into().unwrap_or(u32::MAX))
+                    .unwrap_or(0);
+                self.instruction_pointer + 2
+            }
+            Op::Xor(source) => {
+                values[1] ^= source.evaluate(values);
+                self.instruction_pointer + 2
+            }
+            Op::Mod(source) => {
+                values[1] = source.evaluate(values) % 8;
+                self.instruction_pointer + 2
             }
+            Op::JumpNotZero(target) if values[0] != 0 => target,
+            Op::JumpNotZero(_) => self.instruction_pointer + 2,
+            Op::Out(source) => {
+                output = Some((source.evaluate(values) % 8) as u8);
+                self.instruction_pointer + 2
+            }
+            Op::Invalid => return Err(self.invalid_instruction()),
+        };
+
+        self.steps += 1;
+
+        if let Some(output) = output {
+            self.output.push(output);
+        }
+
+        Ok(Step::Executed(output))
+    }
 
+    fn run(&mut self) -> Result<bool, Error> {
+        while let Step::Executed(output) = self.step()? {
             if let Some(output) = output {
                 if let Some(expected) = self.expected_output {
                     if expected.is_empty() || output != expected[0] {
-                        return false;
+                        return Ok(false);
                     }
 
                     self.expected_output = Some(&expected[1..]);
                 }
-
-                self.output.push(output);
             }
         }
 
-        self.expected_output
+        Ok(self
+            .expected_output
             .map(|expected| expected.is_empty())
-            .unwrap_or(true)
+            .unwrap_or(true))
     }
 }
 
-fn get_output(registers: Registers, program: &[u8]) -> Vec<u8> {
+fn disassemble(program: &[u8]) -> Result<Vec<(usize, Instruction)>, Error> {
+    program
+        .chunks_exact(2)
+        .enumerate()
+        .map(|(index, chunk)| {
+            Instruction::try_decode(chunk[0], chunk[1])
+                .map(|instruction| (index * 2, instruction))
+                .ok_or_else(|| {
+                    err_msg(format!(
+                        "Invalid instruction {},{} at {:02}",
+                        chunk[0],
+                        chunk[1],
+                        index * 2
+                    ))
+                })
+        })
+        .collect()
+}
+
+fn get_output(registers: Registers, program: &Program) -> Result<Vec<u8>, Error> {
     let mut computer = Computer::new(registers, program, None);
-    computer.run();
-    computer.output
+    computer.run()?;
+    Ok(computer.output)
 }
 
-fn program_has_output(registers: Registers, program: &[u8], output: &[u8]) -> bool {
+fn program_has_output(
+    registers: Registers,
+    program: &Program,
+    output: &[u8],
+) -> Result<bool, Error> {
     let mut computer = Computer::new(registers, program, Some(output));
     computer.run()
 }
 
-fn find_initial_reg_value(program: &[u8]) -> u64 {
+// Finds A three bits at a time, starting from the last output, for programs that the
+// analysis can't solve.
+fn search_initial_reg_value(program: &Program) -> Option<u64> {
     let mut candidates = vec![0];
-    for idx in (0..program.len()).rev() {
+    for idx in (0..program.code.len()).rev() {
         candidates = candidates
             .into_iter()
-            .flat_map(|candidate| {
-                (0..8).map(move |d| candidate * 8 + d).filter(|&candidate| {
-                    get_output(Registers::new(candidate, 0, 0), program) == program[idx..]
-                })
+            .flat_map(|candidate| (0..8).map(move |d| candidate * 8 + d))
+            .filter(|&candidate| {
+                get_output(Registers::new(candidate, 0, 0), program)
+                    .is_ok_and(|output| output == program.code[idx..])
             })
             .collect()
     }
 
-    let result = candidates
-        .into_iter()
-        .min()
-        .expect("Failed to find solution");
+    candidates.into_iter().min()
+}
+
+fn find_initial_reg_value(program: &Program) -> Option<u64> {
+    let result = match analysis::find_quine_values(&program.code) {
+        Ok(values) => values.into_iter().next(),
+        Err(_) => search_initial_reg_value(program),
+    }?;
+
+    assert!(program_has_output(Registers::new(result, 0, 0), program, &program.code).unwrap());
+
+    Some(result)
+}
+
+pub fn disassemble_input(data: &str, source: bool) -> Result<String, Error> {
+    let (_, program) = parse_input(data)?;
+    if source {
+        asm::to_source(&program)
+    } else {
+        analysis::describe(&program)
+    }
+}
 
-    assert!(program_has_output(
-        Registers::new(result, 0, 0),
-        program,
+pub fn assemble_input(data: &str) -> Result<String, Error> {
+    asm::assemble(data).map(|program| {
         program
-    ));
+            .iter()
+            .map(|v| v.to_string())
+            .collect::<Vec<_>>()
+            .join(",")
+    })
+}
 
-    result
+pub fn debug_input(data: &str) -> Result<(), Error> {
+    let (registers, program) = parse_input(data)?;
+    debugger::repl(registers, program, std::io::stdin().lock())
 }
 
 pub struct Solver {}
@@ -338,13 +543,101 @@ impl super::Solver for Solver {
         parse_input(&data)
     }
 
-    fn solve((registers, program): Self::Problem) -> (Option<String>, Option<String>) {
+    fn solve((registers, code): Self::Problem) -> (Option<String>, Option<String>) {
+        let program = Program::new(code);
         let part1 = get_output(registers, &program)
-            .iter()
-            .map(|v| v.to_string())
-            .collect::<Vec<_>>()
-            .join(",");
+            .map_err(|err| println!("Failed to run program: {}", err))
+            .ok()
+            .map(|output| {
+                output
+                    .iter()
+                    .map(|v| v.to_string())
+                    .collect::<Vec<_>>()
+                    .join(",")
+            });
         let part2 = find_initial_reg_value(&program);
-        (Some(part1), Some(part2.to_string()))
+        (part1, part2.map(|value| value.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{find_initial_reg_value, get_output, Instruction, Program, Registers};
+    use std::time::Instant;
+
+    fn run_decoding_each_step(mut registers: Registers, program: &[u8]) -> Vec<u8> {
+        let mut instruction_pointer = 0;
+        let mut output = vec![];
+
+        while instruction_pointer + 1 < program.len() {
+            let instruction = Instruction::try_decode(
+                program[instruction_pointer],
+                program[instruction_pointer + 1],
+            )
+            .unwrap();
+            let (next, value) = instruction.execute(&mut registers);
+            instruction_pointer = next.unwrap_or(instruction_pointer + 2);
+            output.extend(value);
+        }
+
+        output
+    }
+
+    #[test]
+    fn test_step_limit() {
+        let program = Program::new(Box::new([1, 7, 3, 0]));
+        assert!(get_output(Registers::new(1, 0, 0), &program).is_err());
+
+        let program = Program::new(Box::new([5, 7]));
+        assert!(get_output(Registers::new(1, 0, 0), &program).is_err());
+    }
+
+    #[test]
+    fn test_initial_reg_value() {
+        let find = |code: &[u8]| find_initial_reg_value(&Program::new(code.into()));
+
+        assert_eq!(find(&[0, 3, 5, 4, 3, 0]), Some(117440));
+        assert_eq!(
+            find(&[2, 4, 1, 1, 7, 5, 1, 5, 4, 0, 0, 3, 5, 5, 3, 0]),
+            Some(164541160582845)
+        );
+
+        // Shifts A by six bits and outputs two values each time round the loop.
+        assert_eq!(
+            find(&[5, 4, 6, 3, 5, 5, 0, 3, 0, 3, 3, 0]),
+            Some(3630356389)
+        );
+
+        // Not a single loop, so found by searching instead.
+        assert_eq!(find(&[0, 3, 5, 4, 3, 0, 1, 0]), Some(2214592));
+    }
+
+    #[test]
+    #[ignore]
+    fn bench_precompiled() {
+        let code: Box<[u8]> = Box::new([2, 4, 1, 1, 7, 5, 1, 5, 4, 0, 0, 3, 5, 5, 3, 0]);
+        let program = Program::new(code.clone());
+        let values = (0..200000u64).map(|a| a << 24 | a);
+
+        let start = Instant::now();
+        let decoded: Vec<_> = values
+            .clone()
+            .map(|a| run_decoding_each_step(Registers::new(a, 0, 0), &code))
+            .collect();
+        let decoding_time = start.elapsed();
+
+        let start = Instant::now();
+        let precompiled: Vec<_> = values
+            .map(|a| get_output(Registers::new(a, 0, 0), &program).unwrap())
+            .collect();
+        let precompiled_time = start.elapsed();
+
+        assert_eq!(decoded, precompiled);
+        println!(
+            "Decoding each step: {:?}, precompiled: {:?} ({:.2}x)",
+            decoding_time,
+            precompiled_time,
+            decoding_time.as_secs_f64() / precompiled_time.as_secs_f64()
+        );
     }
 }
@@ -0,0 +1,335 @@
+use std::{fmt::Display, fmt::Write, rc::Rc};
+
+use failure::{err_msg, Error};
+
+use super::{disassemble, Instruction, Operand, Register, Registers};
+
+const MAX_SHIFT: u64 = 20;
+
+#[derive(Debug, PartialEq, Eq)]
+enum Expr {
+    Initial(Register),
+    Literal(u64),
+    Divide(Rc<Expr>, Rc<Expr>),
+    Xor(Rc<Expr>, Rc<Expr>),
+    Mod(Rc<Expr>),
+}
+
+impl Expr {
+    fn evaluate(&self, registers: &Registers) -> u64 {
+        match self {
+            Expr::Initial(reg) => registers.get(*reg),
+            Expr::Literal(val) => *val,
+            Expr::Divide(numerator, denominator) => {
+                let shift = denominator.evaluate(registers);
+                if shift < 64 {
+                    numerator.evaluate(registers) >> shift
+                } else {
+                    0
+                }
+            }
+            Expr::Xor(left, right) => left.evaluate(registers) ^ right.evaluate(registers),
+            Expr::Mod(operand) => operand.evaluate(registers) % 8,
+        }
+    }
+
+    fn depends_on(&self, register: Register) -> bool {
+        match self {
+            Expr::Initial(reg) => *reg == register,
+            Expr::Literal(_) => false,
+            Expr::Divide(left, right) | Expr::Xor(left, right) => {
+                left.depends_on(register) || right.depends_on(register)
+            }
+            Expr::Mod(operand) => operand.depends_on(register),
+        }
+    }
+
+    fn shift_of(&self, register: Register) -> Option<u64> {
+        match self {
+            Expr::Initial(reg) if *reg == register => Some(0),
+            Expr::Divide(numerator, denominator) => match denominator.as_ref() {
+                Expr::Literal(shift) => numerator.shift_of(register).map(|s| s + shift),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+impl Display for Expr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Expr::Initial(reg) => write!(f, "{}", reg),
+            Expr::Literal(val) => write!(f, "{}", val),
+            Expr::Divide(numerator, denominator) => write!(f, "({} >> {})", numerator, denominator),
+            Expr::Xor(left, right) => write!(f, "({} ^ {})", left, right),
+            Expr::Mod(operand) => write!(f, "({} % 8)", operand),
+        }
+    }
+}
+
+type SymbolicRegisters = [Rc<Expr>; 3];
+
+fn operand_expr(operand: &Operand, registers: &SymbolicRegisters) -> Rc<Expr> {
+    match operand {
+        Operand::Literal(val) => Rc::new(Expr::Literal(*val as u64)),
+        Operand::Register(reg) => registers[reg.index()].clone(),
+    }
+}
+
+fn pseudocode(instruction: &Instruction) -> String {
+    use Instruction::*;
+    match instruction {
+        Divide(numerator, denominator, store) => {
+            format!("{} = {} >> {}", store, numerator, denominator)
+        }
+        Xor(left, right, store) => format!("{} = {} ^ {}", store, left, right),
+        Mod(operand, store) => format!("{} = {} % 8", store, operand),
+        JumpNotZero(target) => format!("if A != 0 goto {:02}", target),
+        Out(operand) => format!("out {} % 8", operand),
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Loop {
+    start: usize,
+    end: usize,
+}
+
+fn find_loops(program: &[u8]) -> Result<Vec<Loop>, Error> {
+    Ok(disassemble(program)?
+        .into_iter()
+        .filter_map(|(address, instruction)| match instruction {
+            Instruction::JumpNotZero(Operand::Literal(target)) if target as usize <= address => {
+                Some(Loop {
+                    start: target as usize,
+                    end: address,
+                })
+            }
+            _ => None,
+        })
+        .collect())
+}
+
+struct Iteration {
+    outputs: Vec<Rc<Expr>>,
+    registers: SymbolicRegisters,
+}
+
+impl Iteration {
+    fn execute(program: &[u8], body: Loop) -> Result<Self, Error> {
+        if !body.start.is_multiple_of(2) {
+            return Err(err_msg(format!("Unaligned jump target {}", body.start)));
+        }
+
+        let mut registers =
+            [Register::A, Register::B, Register::C].map(|reg| Rc::new(Expr::Initial(reg)));
+        let mut outputs = vec![];
+
+        for (address, instruction) in disassemble(program)? {
+            if !(body.start..body.end).contains(&address) {
+                continue;
+            }
+
+            use Instruction::*;
+            match instruction {
+                Divide(numerator, denominator, store) => {
+                    registers[store.index()] = Rc::new(Expr::Divide(
+                        operand_expr(&numerator, &registers),
+                        operand_expr(&denominator, &registers),
+                    ));
+                }
+                Xor(left, right, store) => {
+                    registers[store.index()] = Rc::new(Expr::Xor(
+                        operand_expr(&left, &registers),
+                        operand_expr(&right, &registers),
+                    ));
+                }
+                Mod(operand, store) => {
+                    registers[store.index()] =
+                        Rc::new(Expr::Mod(operand_expr(&operand, &registers)));
+                }
+                JumpNotZero(_) => {
+                    return Err(err_msg(format!(
+                        "Unsupported jump inside loop at {:02}",
+                        address
+                    )));
+                }
+                Out(operand) => {
+                    outputs.push(Rc::new(Expr::Mod(operand_expr(&operand, &registers))))
+                }
+            }
+        }
+
+        Ok(Iteration { outputs, registers })
+    }
+
+    fn carried_registers(&self) -> Vec<Register> {
+        [Register::A, Register::B, Register::C]
+            .into_iter()
+            .filter(|&reg| {
+                self.outputs
+                    .iter()
+                    .chain([&self.registers[Register::A.index()]])
+                    .any(|expr| expr.depends_on(reg))
+            })
+            .collect()
+    }
+
+    fn a_shift(&self) -> Option<u64> {
+        self.registers[Register::A.index()].shift_of(Register::A)
+    }
+
+    fn produces(&self, a: u64, expected: &[u8]) -> bool {
+        let registers = Registers::new(a, 0, 0);
+        self.outputs
+            .iter()
+            .zip(expected)
+            .all(|(output, &value)| output.evaluate(&registers) == value as u64)
+    }
+}
+
+fn describe_loop(description: &mut String, program: &[u8], body: Loop) {
+    writeln!(description, "Loop {:02}-{:02}:", body.start, body.end).unwrap();
+
+    let iteration = match Iteration::execute(program, body) {
+        Ok(iteration) => iteration,
+        Err(err) => {
+            writeln!(description, "  {}", err).unwrap();
+            return;
+        }
+    };
+
+    for output in iteration.outputs.iter() {
+        writeln!(description, "  out {}", output).unwrap();
+    }
+
+    for reg in [Register::A, Register::B, Register::C] {
+        let expr = &iteration.registers[reg.index()];
+        if **expr != Expr::Initial(reg) {
+            writeln!(description, "  {} = {}", reg, expr).unwrap();
+        }
+    }
+
+    match iteration.a_shift() {
+        Some(shift) => writeln!(description, "  A shifts right {} bits per iteration", shift),
+        None => writeln!(description, "  A doesn't shift by a constant amount"),
+    }
+    .unwrap();
+
+    let carried = iteration.carried_registers();
+    writeln!(
+        description,
+        "  Reads {} from the previous iteration",
+        carried
+            .iter()
+            .map(|reg| reg.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    )
+    .unwrap();
+}
+
+pub(super) fn describe(program: &[u8]) -> Result<String, Error> {
+    let mut description = String::new();
+
+    for (address, instruction) in disassemble(program)? {
+        writeln!(
+            description,
+            "{:02}: {:<8} {}",
+            address,
+            instruction.to_string(),
+            pseudocode(&instruction)
+        )
+        .unwrap();
+    }
+
+    for body in find_loops(program)? {
+        writeln!(description).unwrap();
+        describe_loop(&mut description, program, body);
+    }
+
+    Ok(description)
+}
+
+pub(super) fn find_quine_values(program: &[u8]) -> Result<Vec<u64>, Error> {
+    let body = match find_loops(program)?.as_slice() {
+        &[body] if body.start == 0 && body.end + 2 == program.len() => body,
+        _ => return Err(err_msg("Program isn't a single loop")),
+    };
+
+    let iteration = Iteration::execute(program, body)?;
+
+    let shift = iteration
+        .a_shift()
+        .filter(|shift| (1..=MAX_SHIFT).contains(shift))
+        .ok_or_else(|| err_msg("A isn't shifted by a small constant each iteration"))?;
+
+    if iteration
+        .carried_registers()
+        .iter()
+        .any(|&reg| reg != Register::A)
+    {
+        return Err(err_msg(
+            "Loop depends on B or C from the previous iteration",
+        ));
+    }
+
+    let per_iteration = iteration.outputs.len();
+    if per_iteration == 0 || !program.len().is_multiple_of(per_iteration) {
+        return Err(err_msg(format!(
+            "Can't produce {} outputs at {} per iteration",
+            program.len(),
+            per_iteration
+        )));
+    }
+
+    // Work backwards from the last iteration, where A must shift down to zero, adding
+    // the bits shifted out by each earlier iteration.
+    let mut candidates = vec![0u64];
+    for (level, expected) in program.chunks_exact(per_iteration).rev().enumerate() {
+        candidates = candidates
+            .into_iter()
+            .filter(|&prev| level == 0 || prev != 0)
+            .filter(|&prev| prev.leading_zeros() as u64 >= shift)
+            .flat_map(|prev| (0..1u64 << shift).map(move |low| prev << shift | low))
+            .filter(|&a| iteration.produces(a, expected))
+            .collect();
+    }
+
+    candidates.sort();
+    Ok(candidates)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{describe, find_quine_values};
+    use crate::day17::{program_has_output, Program, Registers};
+
+    #[test]
+    fn test_find_quine_values() {
+        let program = Program::new(Box::new([0, 3, 5, 4, 3, 0]));
+        let values = find_quine_values(&program.code).unwrap();
+
+        assert_eq!(values.first(), Some(&117440));
+        for value in values {
+            assert!(
+                program_has_output(Registers::new(value, 0, 0), &program, &program.code).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_unsupported_program() {
+        assert!(find_quine_values(&[1, 1, 5, 5, 0, 3, 3, 0]).is_err());
+        assert!(find_quine_values(&[1, 7, 5, 5, 3, 0]).is_err());
+    }
+
+    #[test]
+    fn test_invalid_operand() {
+        let err = describe(&[5, 7, 3, 0]).unwrap_err();
+        assert_eq!(err.to_string(), "Invalid instruction 5,7 at 00");
+        assert!(find_quine_values(&[0, 3, 5, 7, 3, 0]).is_err());
+        assert!(describe(&[0, 3, 5, 4, 3, 0]).is_ok());
+    }
+}
@@ -0,0 +1,272 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+use failure::{err_msg, Error};
+use nom::{
+    branch::alt,
+    bytes::complete::{tag, take_while},
+    character::complete::{alpha1, alphanumeric1, digit1, space0, space1},
+    combinator::{all_consuming, map, opt, recognize},
+    multi::many0_count,
+    sequence::{pair, preceded, terminated, tuple},
+    IResult,
+};
+
+const MNEMONICS: [&str; 8] = ["adv", "bxl", "bst", "jnz", "bxc", "out", "bdv", "cdv"];
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum OperandKind {
+    Combo,
+    Literal,
+    Ignored,
+}
+
+impl OperandKind {
+    fn for_opcode(opcode: u8) -> Self {
+        match opcode {
+            1 | 3 => OperandKind::Literal,
+            4 => OperandKind::Ignored,
+            _ => OperandKind::Combo,
+        }
+    }
+}
+
+struct Statement<'a> {
+    line: usize,
+    opcode: u8,
+    operand: Option<&'a str>,
+}
+
+fn identifier(input: &str) -> IResult<&str, &str> {
+    recognize(pair(
+        alt((alpha1, tag("_"))),
+        many0_count(alt((alphanumeric1, tag("_")))),
+    ))(input)
+}
+
+fn label(input: &str) -> IResult<&str, &str> {
+    terminated(identifier, pair(space0, tag(":")))(input)
+}
+
+fn instruction(input: &str) -> IResult<&str, (&str, Option<&str>)> {
+    pair(alpha1, opt(preceded(space1, alt((identifier, digit1)))))(input)
+}
+
+#[allow(clippy::type_complexity)]
+fn source_line(input: &str) -> IResult<&str, (Option<&str>, Option<(&str, Option<&str>)>)> {
+    map(
+        all_consuming(tuple((
+            space0,
+            opt(terminated(label, space0)),
+            opt(instruction),
+            space0,
+            opt(pair(tag(";"), take_while(|_| true))),
+        ))),
+        |(_, label, instruction, _, _)| (label, instruction),
+    )(input)
+}
+
+fn parse_value(value: &str, line: usize) -> Result<u8, Error> {
+    value
+        .parse()
+        .ok()
+        .filter(|&value| value < 8)
+        .ok_or_else(|| err_msg(format!("Line {}: invalid 3-bit value {}", line, value)))
+}
+
+fn encode_operand(statement: &Statement<'_>, labels: &HashMap<&str, usize>) -> Result<u8, Error> {
+    let kind = OperandKind::for_opcode(statement.opcode);
+    let mnemonic = MNEMONICS[statement.opcode as usize];
+
+    let Some(operand) = statement.operand else {
+        return if kind == OperandKind::Ignored {
+            Ok(0)
+        } else {
+            Err(err_msg(format!(
+                "Line {}: {} needs an operand",
+                statement.line, mnemonic
+            )))
+        };
+    };
+
+    match (kind, operand) {
+        (OperandKind::Combo, "A") => Ok(4),
+        (OperandKind::Combo, "B") => Ok(5),
+        (OperandKind::Combo, "C") => Ok(6),
+        (OperandKind::Combo, value) => parse_value(value, statement.line).and_then(|value| {
+            if value < 4 {
+                Ok(value)
+            } else {
+                Err(err_msg(format!(
+                    "Line {}: combo literal {} must be below 4",
+                    statement.line, value
+                )))
+            }
+        }),
+        (OperandKind::Literal, value) if statement.opcode == 3 && labels.contains_key(value) => {
+            let address = labels[value];
+            u8::try_from(address)
+                .ok()
+                .filter(|&address| address < 8)
+                .ok_or_else(|| {
+                    err_msg(format!(
+                        "Line {}: label {} at {} is out of jump range",
+                        statement.line, value, address
+                    ))
+                })
+        }
+        (_, value) => parse_value(value, statement.line),
+    }
+}
+
+pub(super) fn assemble(source: &str) -> Result<Box<[u8]>, Error> {
+    let mut labels = HashMap::new();
+    let mut statements = vec![];
+
+    for (index, text) in source.lines().enumerate() {
+        let line = index + 1;
+        let (_, (label, instruction)) = source_line(text)
+            .map_err(|_| err_msg(format!("Line {}: can't parse {:?}", line, text)))?;
+
+        if let Some(label) = label {
+            if labels.insert(label, statements.len() * 2).is_some() {
+                return Err(err_msg(format!("Line {}: duplicate label {}", line, label)));
+            }
+        }
+
+        if let Some((mnemonic, operand)) = instruction {
+            let opcode = MNEMONICS
+                .iter()
+                .position(|&m| m == mnemonic)
+                .ok_or_else(|| err_msg(format!("Line {}: unknown mnemonic {}", line, mnemonic)))?;
+            statements.push(Statement {
+                line,
+                opcode: opcode as u8,
+                operand,
+            });
+        }
+    }
+
+    statements
+        .iter()
+        .map(|statement| Ok([statement.opcode, encode_operand(statement, &labels)?]))
+        .collect::<Result<Vec<_>, Error>>()
+        .map(|pairs| pairs.concat().into_boxed_slice())
+}
+
+fn combo_operand(operand: u8) -> Result<String, Error> {
+    match operand {
+        0..=3 => Ok(operand.to_string()),
+        4 => Ok("A".to_string()),
+        5 => Ok("B".to_string()),
+        6 => Ok("C".to_string()),
+        _ => Err(err_msg(format!("Invalid combo operand {}", operand))),
+    }
+}
+
+pub(super) fn to_source(program: &[u8]) -> Result<String, Error> {
+    if !program.len().is_multiple_of(2) {
+        return Err(err_msg("Program has an odd number of values"));
+    }
+
+    let targets: HashSet<u8> = program
+        .chunks_exact(2)
+        .filter(|chunk| {
+            chunk[0] == 3 && chunk[1].is_multiple_of(2) && (chunk[1] as usize) < program.len()
+        })
+        .map(|chunk| chunk[1])
+        .collect();
+
+    let mut source = String::new();
+
+    for (index, chunk) in program.chunks_exact(2).enumerate() {
+        let address = index * 2;
+        let (opcode, operand) = (chunk[0], chunk[1]);
+
+        if targets.contains(&(address as u8)) {
+            writeln!(source, "l{}:", address).unwrap();
+        }
+
+        let mnemonic = MNEMONICS
+            .get(opcode as usize)
+            .ok_or_else(|| err_msg(format!("Invalid opcode {}", opcode)))?;
+
+        let operand = match OperandKind::for_opcode(opcode) {
+            OperandKind::Combo => Some(combo_operand(operand)?),
+            OperandKind::Literal if opcode == 3 && targets.contains(&operand) => {
+                Some(format!("l{}", operand))
+            }
+            OperandKind::Literal => Some(operand.to_string()),
+            OperandKind::Ignored if operand == 0 => None,
+            OperandKind::Ignored => Some(operand.to_string()),
+        };
+
+        match operand {
+            Some(operand) => writeln!(source, "    {} {}", mnemonic, operand),
+            None => writeln!(source, "    {}", mnemonic),
+        }
+        .unwrap();
+    }
+
+    Ok(source)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{assemble, to_source};
+
+    #[test]
+    fn test_assemble() {
+        let source = "\
+start:
+    bst A      ; B = A % 8
+    bxl 1
+    cdv B
+    bxl 5
+    bxc
+    adv 3
+    out B
+    jnz start
+";
+        assert_eq!(
+            assemble(source).unwrap().as_ref(),
+            &[2, 4, 1, 1, 7, 5, 1, 5, 4, 0, 0, 3, 5, 5, 3, 0]
+        );
+    }
+
+    #[test]
+    fn test_labels() {
+        let source = "\
+loop_start:
+    out A
+    adv 1
+    jnz loop_start
+";
+        assert_eq!(assemble(source).unwrap().as_ref(), &[5, 4, 0, 1, 3, 0]);
+        assert!(assemble("_end: jnz _end").is_ok());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for program in [
+            &[0, 1, 5, 4, 3, 0][..],
+            &[0, 3, 5, 4, 3, 0],
+            &[2, 4, 1, 1, 7, 5, 1, 5, 4, 3, 0, 3, 5, 5, 3, 0],
+            &[3, 4, 5, 6, 3, 7, 1, 2],
+        ] {
+            let source = to_source(program).unwrap();
+            assert_eq!(assemble(&source).unwrap().as_ref(), program);
+            assert_eq!(to_source(&assemble(&source).unwrap()).unwrap(), source);
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(assemble("foo 1").is_err());
+        assert!(assemble("adv 4").is_err());
+        assert!(assemble("bxl 8").is_err());
+        assert!(assemble("jnz nowhere").is_err());
+        assert!(assemble("a:\na: out A").is_err());
+        assert!(to_source(&[5, 7]).is_err());
+    }
+}
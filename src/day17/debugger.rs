@@ -0,0 +1,465 @@
+use std::{
+    collections::{BTreeSet, VecDeque},
+    fmt::Display,
+    io::{stdout, BufRead, Write},
+};
+
+use failure::{err_msg, Error};
+
+use super::{
+    parse::parse_program, Computer, Instruction, Program, Register, Registers, Step, STEP_LIMIT,
+};
+
+const MAX_HISTORY: usize = 100000;
+
+const HELP: &str = "\
+s, step [n]       execute the next n instructions
+b, back [n]       step back through the last n instructions
+c, continue       run until a breakpoint, watchpoint or the program halts
+o, output         run until the next output
+break <addr>      toggle a breakpoint on an instruction address
+watch <reg>       toggle a watchpoint on a register
+r, regs           show the registers
+set <reg> <val>   set a register
+l, list           disassemble the program
+out               show the output so far
+reset             restart the program
+load <program>    load a new comma-separated program
+q, quit           exit the debugger";
+
+#[derive(Clone, Copy)]
+struct Snapshot {
+    registers: Registers,
+    instruction_pointer: usize,
+    output_len: usize,
+    steps: usize,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(super) enum Stop {
+    Stepped,
+    Halted,
+    Breakpoint(usize),
+    Watchpoint(Register, u64, u64),
+    Output(u8),
+    Error(String),
+}
+
+impl Display for Stop {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Stop::Stepped => Ok(()),
+            Stop::Halted => write!(f, "Program halted"),
+            Stop::Breakpoint(address) => write!(f, "Breakpoint at {:02}", address),
+            Stop::Watchpoint(reg, old, new) => write!(f, "{} changed from {} to {}", reg, old, new),
+            Stop::Output(value) => write!(f, "Output {}", value),
+            Stop::Error(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+pub(super) struct Debugger<'a> {
+    computer: Computer<'a>,
+    initial_registers: Registers,
+    breakpoints: BTreeSet<usize>,
+    watchpoints: Vec<Register>,
+    history: VecDeque<Snapshot>,
+}
+
+impl<'a> Debugger<'a> {
+    pub(super) fn new(registers: Registers, program: &'a Program) -> Self {
+        let mut computer = Computer::new(registers, program, None);
+        computer.step_limit = None;
+
+        Debugger {
+            computer,
+            initial_registers: registers,
+            breakpoints: BTreeSet::new(),
+            watchpoints: vec![],
+            history: VecDeque::new(),
+        }
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            registers: self.computer.registers,
+            instruction_pointer: self.computer.instruction_pointer,
+            output_len: self.computer.output.len(),
+            steps: self.computer.steps,
+        }
+    }
+
+    fn restore(&mut self, snapshot: Snapshot) {
+        self.computer.registers = snapshot.registers;
+        self.computer.instruction_pointer = snapshot.instruction_pointer;
+        self.computer.output.truncate(snapshot.output_len);
+        self.computer.steps = snapshot.steps;
+    }
+
+    pub(super) fn registers(&self) -> Registers {
+        self.computer.registers
+    }
+
+    pub(super) fn set_register(&mut self, register: Register, value: u64) {
+        self.computer.registers.set(register, value);
+    }
+
+    pub(super) fn output(&self) -> &[u8] {
+        &self.computer.output
+    }
+
+    pub(super) fn toggle_breakpoint(&mut self, address: usize) -> bool {
+        if !self.breakpoints.remove(&address) {
+            self.breakpoints.insert(address);
+            true
+        } else {
+            false
+        }
+    }
+
+    pub(super) fn toggle_watchpoint(&mut self, register: Register) -> bool {
+        if self.watchpoints.contains(&register) {
+            self.watchpoints.retain(|&reg| reg != register);
+            false
+        } else {
+            self.watchpoints.push(register);
+            true
+        }
+    }
+
+    pub(super) fn step(&mut self) -> Stop {
+        let before = self.snapshot();
+
+        let output = match self.computer.step() {
+            Ok(Step::Executed(output)) => output,
+            Ok(Step::Halted) => return Stop::Halted,
+            Err(err) => return Stop::Error(err.to_string()),
+        };
+
+        if self.history.len() == MAX_HISTORY {
+            self.history.pop_front();
+        }
+        self.history.push_back(before);
+
+        let registers = self.computer.registers;
+        if let Some(&reg) = self
+            .watchpoints
+            .iter()
+            .find(|&&reg| before.registers.get(reg) != registers.get(reg))
+        {
+            Stop::Watchpoint(reg, before.registers.get(reg), registers.get(reg))
+        } else if self
+            .breakpoints
+            .contains(&self.computer.instruction_pointer)
+        {
+            Stop::Breakpoint(self.computer.instruction_pointer)
+        } else if let Some(output) = output {
+            Stop::Output(output)
+        } else {
+            Stop::Stepped
+        }
+    }
+
+    pub(super) fn step_back(&mut self) -> bool {
+        if let Some(snapshot) = self.history.pop_back() {
+            self.restore(snapshot);
+            true
+        } else {
+            false
+        }
+    }
+
+    pub(super) fn resume(&mut self) -> Stop {
+        for _ in 0..STEP_LIMIT {
+            match self.step() {
+                Stop::Stepped | Stop::Output(_) => {}
+                stop => return stop,
+            }
+        }
+
+        Stop::Error(format!("No stop within {} steps", STEP_LIMIT))
+    }
+
+    pub(super) fn run_until_output(&mut self) -> Stop {
+        for _ in 0..STEP_LIMIT {
+            match self.step() {
+                Stop::Stepped => {}
+                stop => return stop,
+            }
+        }
+
+        Stop::Error(format!("No output within {} steps", STEP_LIMIT))
+    }
+
+    pub(super) fn restart(&mut self) {
+        self.restore(Snapshot {
+            registers: self.initial_registers,
+            instruction_pointer: 0,
+            output_len: 0,
+            steps: 0,
+        });
+        self.history.clear();
+    }
+
+    fn show_position(&self) {
+        match self.computer.next_instruction() {
+            Ok(Some(instruction)) => println!(
+                "{:02} {} {}",
+                self.computer.instruction_pointer, instruction, self.computer.registers
+            ),
+            Ok(None) => println!(
+                "{:02} halted {}",
+                self.computer.instruction_pointer, self.computer.registers
+            ),
+            Err(err) => println!("{}", err),
+        }
+    }
+
+    fn show_listing(&self) {
+        for (index, chunk) in self.computer.program.code.chunks_exact(2).enumerate() {
+            let address = index * 2;
+            let marker = if address == self.computer.instruction_pointer {
+                "=>"
+            } else {
+                "  "
+            };
+            let breakpoint = if self.breakpoints.contains(&address) {
+                "*"
+            } else {
+                " "
+            };
+            match Instruction::try_decode(chunk[0], chunk[1]) {
+                Some(instruction) => {
+                    println!("{}{} {:02}: {}", marker, breakpoint, address, instruction)
+                }
+                None => println!("{}{} {:02}: invalid", marker, breakpoint, address),
+            }
+        }
+    }
+}
+
+enum Outcome {
+    Quit,
+    Load(Box<[u8]>),
+}
+
+fn parse_count(args: &[&str]) -> Result<usize, Error> {
+    match args {
+        [] => Ok(1),
+        [count] => count
+            .parse()
+            .map_err(|_| err_msg(format!("Invalid count {}", count))),
+        _ => Err(err_msg("Too many arguments")),
+    }
+}
+
+fn execute_command(debugger: &mut Debugger<'_>, words: &[&str]) -> Result<Option<Outcome>, Error> {
+    match words {
+        [] => {}
+        ["s" | "step", args @ ..] => {
+            for _ in 0..parse_count(args)? {
+                let stop = debugger.step();
+                if stop != Stop::Stepped {
+                    println!("{}", stop);
+                    break;
+                }
+            }
+            debugger.show_position();
+        }
+        ["b" | "back", args @ ..] => {
+            for _ in 0..parse_count(args)? {
+                if !debugger.step_back() {
+                    println!("No more history");
+                    break;
+                }
+            }
+            debugger.show_position();
+        }
+        ["c" | "continue"] => {
+            println!("{}", debugger.resume());
+            debugger.show_position();
+        }
+        ["o" | "output"] => {
+            println!("{}", debugger.run_until_output());
+            debugger.show_position();
+        }
+        ["break", address] => {
+            let address = address
+                .parse()
+                .map_err(|_| err_msg(format!("Invalid address {}", address)))?;
+            if debugger.toggle_breakpoint(address) {
+                println!("Breakpoint set at {:02}", address);
+            } else {
+                println!("Breakpoint removed from {:02}", address);
+            }
+        }
+        ["watch", register] => {
+            let register: Register = register.parse()?;
+            if debugger.toggle_watchpoint(register) {
+                println!("Watching {}", register);
+            } else {
+                println!("No longer watching {}", register);
+            }
+        }
+        ["r" | "regs"] => {
+            let registers = debugger.registers();
+            for reg in [Register::A, Register::B, Register::C] {
+                println!("{} = {}", reg, registers.get(reg));
+            }
+        }
+        ["set", register, value] => {
+            let register: Register = register.parse()?;
+            let value = value
+                .parse()
+                .map_err(|_| err_msg(format!("Invalid value {}", value)))?;
+            debugger.set_register(register, value);
+            debugger.show_position();
+        }
+        ["l" | "list"] => debugger.show_listing(),
+        ["out"] => println!(
+            "{}",
+            debugger
+                .output()
+                .iter()
+                .map(|v| v.to_string())
+                .collect::<Vec<_>>()
+                .join(",")
+        ),
+        ["reset"] => {
+            debugger.restart();
+            debugger.show_position();
+        }
+        ["load", program] => return Ok(Some(Outcome::Load(parse_program(program)?))),
+        ["h" | "help"] => println!("{}", HELP),
+        ["q" | "quit"] => return Ok(Some(Outcome::Quit)),
+        _ => return Err(err_msg(format!("Unknown command: {}", words.join(" ")))),
+    }
+
+    Ok(None)
+}
+
+fn session(
+    registers: Registers,
+    program: &[u8],
+    lines: &mut impl Iterator<Item = std::io::Result<String>>,
+) -> Result<Outcome, Error> {
+    let program = Program::new(program.into());
+    let mut debugger = Debugger::new(registers, &program);
+    debugger.show_position();
+
+    loop {
+        print!("> ");
+        stdout().flush()?;
+
+        let Some(line) = lines.next() else {
+            return Ok(Outcome::Quit);
+        };
+
+        let line = line?;
+        let words: Vec<_> = line.split_whitespace().collect();
+
+        match execute_command(&mut debugger, &words) {
+            Ok(Some(outcome)) => return Ok(outcome),
+            Ok(None) => {}
+            Err(err) => println!("{}", err),
+        }
+    }
+}
+
+pub(super) fn repl(
+    registers: Registers,
+    mut program: Box<[u8]>,
+    input: impl BufRead,
+) -> Result<(), Error> {
+    let mut lines = input.lines();
+
+    loop {
+        match session(registers, &program, &mut lines)? {
+            Outcome::Quit => return Ok(()),
+            Outcome::Load(new_program) => program = new_program,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::{execute_command, repl, Debugger, Outcome, Stop};
+    use crate::day17::{Program, Register, Registers};
+
+    #[test]
+    fn test_debugger() {
+        let program = Program::new(Box::new([0, 1, 5, 4, 3, 0]));
+        let mut debugger = Debugger::new(Registers::new(10, 0, 0), &program);
+
+        assert_eq!(debugger.step(), Stop::Stepped);
+        assert_eq!(debugger.step(), Stop::Output(5));
+        assert!(debugger.step_back());
+        assert_eq!(debugger.output(), &[] as &[u8]);
+        assert_eq!(debugger.computer.steps, 1);
+        assert_eq!(debugger.run_until_output(), Stop::Output(5));
+
+        debugger.toggle_breakpoint(2);
+        assert_eq!(debugger.resume(), Stop::Breakpoint(2));
+        assert_eq!(debugger.registers().get(Register::A), 2);
+
+        debugger.toggle_breakpoint(2);
+        debugger.toggle_watchpoint(Register::A);
+        assert_eq!(debugger.resume(), Stop::Watchpoint(Register::A, 2, 1));
+        assert_eq!(debugger.resume(), Stop::Watchpoint(Register::A, 1, 0));
+        assert_eq!(debugger.resume(), Stop::Halted);
+        assert_eq!(debugger.output(), &[5, 2, 1, 0]);
+
+        debugger.restart();
+        assert_eq!(debugger.registers(), Registers::new(10, 0, 0));
+        assert_eq!(debugger.computer.steps, 0);
+    }
+
+    #[test]
+    fn test_commands() {
+        let program = Program::new(Box::new([0, 1, 5, 4, 3, 0]));
+        let mut debugger = Debugger::new(Registers::new(10, 0, 0), &program);
+        let mut run = |command: &str| {
+            let words: Vec<_> = command.split_whitespace().collect();
+            execute_command(&mut debugger, &words)
+        };
+
+        assert!(run("step 2").unwrap().is_none());
+        assert!(run("back").unwrap().is_none());
+        assert!(run("set b 7").unwrap().is_none());
+        assert!(run("break 4").unwrap().is_none());
+        assert!(run("c").unwrap().is_none());
+        assert!(matches!(run("load 5,4"), Ok(Some(Outcome::Load(_)))));
+        assert!(matches!(run("q"), Ok(Some(Outcome::Quit))));
+
+        assert!(run("step x").is_err());
+        assert!(run("break").is_err());
+        assert!(run("set d 1").is_err());
+        assert!(run("load 8").is_err());
+        assert!(run("jump 4").is_err());
+
+        assert_eq!(debugger.registers(), Registers::new(5, 7, 0));
+        assert_eq!(debugger.computer.instruction_pointer, 4);
+        assert_eq!(debugger.output(), &[5]);
+    }
+
+    #[test]
+    fn test_repl() {
+        let input = Cursor::new("s\nbogus\nload 5,4\ns\nout\nquit\n");
+        assert!(repl(
+            Registers::new(10, 0, 0),
+            Box::new([0, 1, 5, 4, 3, 0]),
+            input
+        )
+        .is_ok());
+
+        // Runs out of input without quitting.
+        assert!(repl(
+            Registers::new(10, 0, 0),
+            Box::new([5, 4]),
+            Cursor::new("c\n")
+        )
+        .is_ok());
+    }
+}
@@ -1,75 +1,263 @@
 use std::collections::{HashMap, HashSet};
 
-use failure::Error;
+use failure::{err_msg, Error};
+use itertools::Itertools;
 
-fn num_arrangements(pattern: &str, towels: &[String]) -> usize {
-    let mut arrangements = HashMap::new();
-    arrangements.insert("", 1);
+use crate::parallel;
 
-    for index in (0..pattern.len()).rev() {
-        let remaining = &pattern[index..];
+#[derive(Debug, Default)]
+struct TrieNode {
+    children: HashMap<u8, usize>,
+    towel: Option<usize>,
+}
+
+// The towels in a trie, so all the towels that match at a position in a pattern can be
+// found in one pass.
+pub struct Towels {
+    towels: Box<[String]>,
+    nodes: Vec<TrieNode>,
+}
 
-        let mut num = 0;
+impl Towels {
+    fn new(towels: Box<[String]>) -> Self {
+        let mut nodes = vec![TrieNode::default()];
 
-        for towel in towels {
-            if remaining.starts_with(towel) {
-                let next = &remaining[towel.len()..];
-                num += arrangements[next];
+        for (index, towel) in towels.iter().enumerate() {
+            let mut node = 0;
+            for &colour in towel.as_bytes() {
+                let next = nodes.len();
+                node = *nodes[node].children.entry(colour).or_insert(next);
+                if node == next {
+                    nodes.push(TrieNode::default());
+                }
             }
+            nodes[node].towel = Some(index);
         }
 
-        arrangements.insert(remaining, num);
+        Towels { towels, nodes }
     }
 
-    arrangements[pattern]
-}
-
-fn is_possible(pattern: &str, towels: &[String]) -> bool {
-    let mut stack = vec![(pattern)];
+    // The towels that match the pattern at the start position.
+    fn matches<'a>(&'a self, pattern: &'a str, start: usize) -> impl Iterator<Item = usize> + 'a {
+        pattern.as_bytes()[start..]
+            .iter()
+            .scan(0, |node, colour| {
+                *node = *self.nodes[*node].children.get(colour)?;
+                Some(*node)
+            })
+            .filter_map(|node| self.nodes[node].towel)
+    }
 
-    let mut added = HashSet::new();
-    added.insert(pattern);
+    // The number of arrangements of each suffix of the pattern.
+    fn suffix_arrangements(&self, pattern: &str) -> Vec<usize> {
+        let mut counts = vec![0; pattern.len() + 1];
+        counts[pattern.len()] = 1;
 
-    while let Some(remaining) = stack.pop() {
-        if remaining.is_empty() {
-            return true;
+        for start in (0..pattern.len()).rev() {
+            counts[start] = self
+                .matches(pattern, start)
+                .map(|towel| counts[start + self.towels[towel].len()])
+                .sum();
         }
 
-        for towel in towels {
-            if remaining.starts_with(towel) {
-                let next: &str = &remaining[towel.len()..];
-                if !added.contains(next) {
-                    added.insert(next);
-                    stack.push(next);
+        counts
+    }
+
+    fn is_possible(&self, pattern: &str) -> bool {
+        self.num_arrangements(pattern) > 0
+    }
+
+    fn num_arrangements(&self, pattern: &str) -> usize {
+        self.suffix_arrangements(pattern)[0]
+    }
+
+    // Each arrangement of towels that makes the pattern, generated as needed.
+    fn arrangements<'a>(&'a self, pattern: &'a str) -> impl Iterator<Item = Vec<&'a str>> + 'a {
+        let counts = self.suffix_arrangements(pattern);
+        let mut stack = vec![(0, vec![])];
+
+        std::iter::from_fn(move || {
+            while let Some((start, arrangement)) = stack.pop() {
+                if start == pattern.len() {
+                    return Some(arrangement);
+                }
+
+                // Only follow towels that leave something that can still be made.
+                let next: Vec<_> = self
+                    .matches(pattern, start)
+                    .map(|towel| (start + self.towels[towel].len(), towel))
+                    .filter(|&(end, _)| counts[end] > 0)
+                    .collect();
+
+                for (end, towel) in next.into_iter().rev() {
+                    let mut arrangement = arrangement.clone();
+                    arrangement.push(self.towels[towel].as_str());
+                    stack.push((end, arrangement));
                 }
             }
+
+            None
+        })
+    }
+
+    fn shortest_arrangement(&self, pattern: &str) -> Option<Vec<&str>> {
+        // For each suffix, the fewest towels needed and the first towel to use.
+        let mut best: Vec<Option<(usize, usize)>> = vec![None; pattern.len() + 1];
+        best[pattern.len()] = Some((0, 0));
+
+        for start in (0..pattern.len()).rev() {
+            best[start] = self
+                .matches(pattern, start)
+                .filter_map(|towel| {
+                    let (num, _) = best[start + self.towels[towel].len()]?;
+                    Some((num + 1, towel))
+                })
+                .min();
+        }
+
+        best[0]?;
+        let mut arrangement = vec![];
+        let mut start = 0;
+        while let Some((_, towel)) = best[start].filter(|_| start < pattern.len()) {
+            arrangement.push(self.towels[towel].as_str());
+            start += self.towels[towel].len();
         }
+        Some(arrangement)
     }
 
-    false
+    // The arrangement using the fewest different towels, found by a depth first search
+    // that tries towels it's already used first and gives up on anything that can't beat
+    // the best so far.
+    fn fewest_towels_arrangement(&self, pattern: &str) -> Option<Vec<&str>> {
+        struct Search<'a> {
+            towels: &'a Towels,
+            pattern: &'a str,
+            counts: Vec<usize>,
+            failed: HashSet<(usize, Vec<usize>)>,
+            best: Option<(usize, Vec<usize>)>,
+        }
+
+        impl Search<'_> {
+            fn search(
+                &mut self,
+                start: usize,
+                arrangement: &mut Vec<usize>,
+                used: &mut Vec<usize>,
+            ) {
+                if self
+                    .best
+                    .as_ref()
+                    .is_some_and(|(num, _)| used.len() >= *num)
+                {
+                    return;
+                }
+
+                if start == self.pattern.len() {
+                    self.best = Some((used.len(), arrangement.clone()));
+                    return;
+                }
+
+                if self.failed.contains(&(start, used.clone())) {
+                    return;
+                }
+
+                let best_before = self.best.as_ref().map(|(num, _)| *num);
+                let towels = self
+                    .towels
+                    .matches(self.pattern, start)
+                    .filter(|&towel| self.counts[start + self.towels.towels[towel].len()] > 0)
+                    .sorted_by_key(|towel| !used.contains(towel))
+                    .collect::<Vec<_>>();
+
+                for towel in towels {
+                    let new = !used.contains(&towel);
+                    if new {
+                        let position = used.binary_search(&towel).unwrap_err();
+                        used.insert(position, towel);
+                    }
+
+                    arrangement.push(towel);
+                    self.search(start + self.towels.towels[towel].len(), arrangement, used);
+                    arrangement.pop();
+
+                    if new {
+                        used.retain(|&other| other != towel);
+                    }
+                }
+
+                if self.best.as_ref().map(|(num, _)| *num) == best_before {
+                    self.failed.insert((start, used.clone()));
+                }
+            }
+        }
+
+        let mut search = Search {
+            towels: self,
+            pattern,
+            counts: self.suffix_arrangements(pattern),
+            failed: HashSet::new(),
+            best: None,
+        };
+        search.search(0, &mut vec![], &mut vec![]);
+
+        search.best.map(|(_, arrangement)| {
+            arrangement
+                .into_iter()
+                .map(|towel| self.towels[towel].as_str())
+                .collect()
+        })
+    }
 }
 
-fn find_num_possible(towels: &[String], patterns: &[String]) -> usize {
-    patterns
-        .iter()
-        .filter(|pattern| is_possible(pattern, towels))
-        .count()
+fn find_num_possible(towels: &Towels, patterns: &[String]) -> usize {
+    parallel::count(patterns, |pattern| towels.is_possible(pattern))
 }
 
-fn find_num_arrangements(towels: &[String], patterns: &[String]) -> usize {
-    patterns
-        .iter()
-        .map(|pattern| num_arrangements(pattern, towels))
+fn find_num_arrangements(towels: &Towels, patterns: &[String]) -> usize {
+    parallel::map(patterns, |pattern| towels.num_arrangements(pattern))
+        .into_iter()
         .sum()
 }
 
+// Describes how each pattern can be made, listing up to limit of its arrangements.
+pub fn arrange_input(data: String, limit: usize) -> Result<String, Error> {
+    let (towels, patterns) = <Solver as super::Solver>::parse_input(data)?;
+
+    let mut output = vec![];
+    for pattern in patterns.iter() {
+        let (Some(shortest), Some(fewest)) = (
+            towels.shortest_arrangement(pattern),
+            towels.fewest_towels_arrangement(pattern),
+        ) else {
+            output.push(format!("{}: impossible", pattern));
+            continue;
+        };
+
+        output.push(format!(
+            "{}: {} arrangements",
+            pattern,
+            towels.num_arrangements(pattern)
+        ));
+        output.push(format!("  shortest: {}", shortest.join(" ")));
+        output.push(format!("  fewest towels: {}", fewest.join(" ")));
+        for arrangement in towels.arrangements(pattern).take(limit) {
+            output.push(format!("  {}", arrangement.join(" ")));
+        }
+    }
+
+    Ok(output.join("\n"))
+}
+
 pub struct Solver {}
 
 impl super::Solver for Solver {
-    type Problem = (Box<[String]>, Box<[String]>);
+    type Problem = (Towels, Box<[String]>);
 
     fn parse_input(data: String) -> Result<Self::Problem, Error> {
         let lines: Vec<_> = data.lines().collect();
+        if lines.len() < 2 {
+            return Err(err_msg("Expected towels, a blank line, then patterns"));
+        }
 
         let towels = lines[0]
             .split(", ")
@@ -82,7 +270,7 @@ impl super::Solver for Solver {
             .collect::<Vec<_>>()
             .into_boxed_slice();
 
-        Ok((towels, patterns))
+        Ok((Towels::new(towels), patterns))
     }
 
     fn solve((towels, patterns): Self::Problem) -> (Option<String>, Option<String>) {
@@ -91,3 +279,61 @@ impl super::Solver for Solver {
         (Some(part1.to_string()), Some(part2.to_string()))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{find_num_arrangements, find_num_possible, Solver};
+    use crate::Solver as _;
+
+    const EXAMPLE: &str = "\
+r, wr, b, g, bwu, rb, gb, br
+
+brwrr
+bggr
+gbbr
+rrbgbr
+ubwu
+bwurrg
+brgr
+bbrgwb
+";
+
+    #[test]
+    fn test_arrangements() {
+        let (towels, patterns) = Solver::parse_input(EXAMPLE.to_string()).unwrap();
+        assert_eq!(find_num_possible(&towels, &patterns), 6);
+        assert_eq!(find_num_arrangements(&towels, &patterns), 16);
+
+        let arrangements: Vec<_> = towels.arrangements("gbbr").collect();
+        assert_eq!(
+            arrangements,
+            vec![
+                vec!["g", "b", "b", "r"],
+                vec!["g", "b", "br"],
+                vec!["gb", "b", "r"],
+                vec!["gb", "br"],
+            ]
+        );
+        assert_eq!(towels.arrangements("ubwu").next(), None);
+
+        assert_eq!(towels.shortest_arrangement("rrbgbr").unwrap().len(), 4);
+        assert_eq!(towels.shortest_arrangement("bbrgwb"), None);
+    }
+
+    #[test]
+    fn test_fewest_towels() {
+        let (towels, _) = Solver::parse_input("ab, a, b, ba\n\nx\n".to_string()).unwrap();
+        assert_eq!(
+            towels.fewest_towels_arrangement("ababab").unwrap(),
+            vec!["ab", "ab", "ab"]
+        );
+        assert_eq!(
+            towels.fewest_towels_arrangement("aabb").unwrap(),
+            vec!["a", "a", "b", "b"]
+        );
+        assert_eq!(
+            towels.shortest_arrangement("aabb").unwrap(),
+            vec!["a", "ab", "b"]
+        );
+    }
+}
@@ -1,6 +1,8 @@
+use std::cmp::Reverse;
 use std::collections::{HashMap, HashSet};
 
 use failure::{err_msg, Error};
+use itertools::Itertools;
 
 use crate::common::{find_all_symbols_in_grid, find_symbol_in_grid, Position};
 
@@ -30,34 +32,112 @@ fn find_distances_from(pos: Position, walls: &HashSet<Position>) -> HashMap<Posi
     distances
 }
 
-fn find_cheats_better_than(
+// Passing through walls from start to end, saving time on the best route.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cheat {
     start: Position,
     end: Position,
-    walls: &HashSet<Position>,
-    cheat_ps: u64,
-    min_improvement: u64,
-) -> usize {
-    let from_start = find_distances_from(start, walls);
-    let from_end = find_distances_from(end, walls);
-    let from_end_ref = &from_end;
+    saving: u64,
+}
+
+// The distances to and from every reachable position, so any route through a maze with
+// branches can be used either side of a cheat.
+struct Race {
+    from_start: HashMap<Position, u64>,
+    from_end: HashMap<Position, u64>,
+    best: u64,
+}
 
-    let best = *from_start.get(&end).unwrap();
+impl Race {
+    fn new(start: Position, end: Position, walls: &HashSet<Position>) -> Result<Self, Error> {
+        let from_start = find_distances_from(start, walls);
+        let from_end = find_distances_from(end, walls);
 
-    from_start
-        .iter()
-        .flat_map(|(cheat_start, t1)| {
+        let best = *from_start
+            .get(&end)
+            .ok_or_else(|| err_msg("No route from the start to the end"))?;
+
+        Ok(Race {
+            from_start,
+            from_end,
+            best,
+        })
+    }
+
+    // Every cheat of up to cheat_ps picoseconds that saves time, in no particular order.
+    fn cheats(&self, cheat_ps: u64) -> impl Iterator<Item = Cheat> + '_ {
+        self.from_start.iter().flat_map(move |(&cheat_start, t1)| {
             cheat_start
                 .within_range(cheat_ps as i64)
                 .filter_map(move |cheat_end| {
-                    from_end_ref
-                        .get(&cheat_end)
-                        .map(|t2| t1 + t2 + cheat_start.manhattan_distance_to(&cheat_end))
+                    let t2 = self.from_end.get(&cheat_end)?;
+                    let time = t1 + t2 + cheat_start.manhattan_distance_to(&cheat_end);
+                    let saving = self.best.checked_sub(time).filter(|&saving| saving > 0)?;
+                    Some(Cheat {
+                        start: cheat_start,
+                        end: cheat_end,
+                        saving,
+                    })
                 })
         })
-        .filter(|t| t + min_improvement <= best)
+    }
+}
+
+// Every cheat of up to cheat_ps picoseconds that saves time, biggest saving first.
+fn find_cheats(
+    start: Position,
+    end: Position,
+    walls: &HashSet<Position>,
+    cheat_ps: u64,
+) -> Result<Vec<Cheat>, Error> {
+    let mut cheats: Vec<_> = Race::new(start, end, walls)?.cheats(cheat_ps).collect();
+
+    cheats.sort_by_key(|cheat| {
+        (
+            Reverse(cheat.saving),
+            cheat.start.y,
+            cheat.start.x,
+            cheat.end.y,
+            cheat.end.x,
+        )
+    });
+    Ok(cheats)
+}
+
+// The number of cheats that save each amount of time, listed like the puzzle does.
+fn savings_histogram(cheats: &[Cheat], min_saving: u64) -> String {
+    cheats
+        .iter()
+        .map(|cheat| cheat.saving)
+        .filter(|&saving| saving >= min_saving)
+        .counts()
+        .into_iter()
+        .sorted()
+        .map(|(saving, count)| {
+            if count == 1 {
+                format!("- There is one cheat that saves {} picoseconds.", saving)
+            } else {
+                format!(
+                    "- There are {} cheats that save {} picoseconds.",
+                    count, saving
+                )
+            }
+        })
+        .join("\n")
+}
+
+fn count_cheats_better_than(race: &Race, cheat_ps: u64, min_improvement: u64) -> usize {
+    race.cheats(cheat_ps)
+        .filter(|cheat| cheat.saving >= min_improvement)
         .count()
 }
 
+pub fn cheats_input(data: String, cheat_ps: u64, min_saving: u64) -> Result<String, Error> {
+    let (start, end, walls) = <Solver as super::Solver>::parse_input(data)?;
+    let cheats = find_cheats(start, end, &walls, cheat_ps)?;
+    Ok(savings_histogram(&cheats, min_saving))
+}
+
 pub struct Solver {}
 
 impl super::Solver for Solver {
@@ -73,8 +153,85 @@ impl super::Solver for Solver {
     }
 
     fn solve((start, end, walls): Self::Problem) -> (Option<String>, Option<String>) {
-        let part1 = find_cheats_better_than(start, end, &walls, 2, 100);
-        let part2 = find_cheats_better_than(start, end, &walls, 20, 100);
-        (Some(part1.to_string()), Some(part2.to_string()))
+        match Race::new(start, end, &walls) {
+            Ok(race) => {
+                let part1 = count_cheats_better_than(&race, 2, 100);
+                let part2 = count_cheats_better_than(&race, 20, 100);
+                (Some(part1.to_string()), Some(part2.to_string()))
+            }
+            Err(err) => {
+                println!("{}", err);
+                (None, None)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{count_cheats_better_than, find_cheats, savings_histogram, Race, Solver};
+    use crate::Solver as _;
+
+    const EXAMPLE: &str = "###############
+#...#...#.....#
+#.#.#.#.#.###.#
+#S#...#.#.#...#
+#######.#.#.###
+#######.#.#...#
+#######.#.###.#
+###..E#...#...#
+###.#######.###
+#...###...#...#
+#.#####.#.###.#
+#.#...#.#.#...#
+#.#.#.#.#.#.###
+#...#...#...###
+###############";
+
+    #[test]
+    fn test_histogram() {
+        let (start, end, walls) = Solver::parse_input(EXAMPLE.to_string()).unwrap();
+
+        let cheats = find_cheats(start, end, &walls, 2).unwrap();
+        assert_eq!(
+            savings_histogram(&cheats, 1),
+            "- There are 14 cheats that save 2 picoseconds.
+- There are 14 cheats that save 4 picoseconds.
+- There are 2 cheats that save 6 picoseconds.
+- There are 4 cheats that save 8 picoseconds.
+- There are 2 cheats that save 10 picoseconds.
+- There are 3 cheats that save 12 picoseconds.
+- There is one cheat that saves 20 picoseconds.
+- There is one cheat that saves 36 picoseconds.
+- There is one cheat that saves 38 picoseconds.
+- There is one cheat that saves 40 picoseconds.
+- There is one cheat that saves 64 picoseconds."
+        );
+        assert_eq!(cheats[0].saving, 64);
+
+        let race = Race::new(start, end, &walls).unwrap();
+        assert_eq!(count_cheats_better_than(&race, 20, 76), 3);
+        assert_eq!(count_cheats_better_than(&race, 2, 1), cheats.len());
+
+        let cheats = find_cheats(start, end, &walls, 20).unwrap();
+        assert!(savings_histogram(&cheats, 50)
+            .starts_with("- There are 32 cheats that save 50 picoseconds."));
+    }
+
+    #[test]
+    fn test_branches() {
+        let maze = "#######
+#S....#
+#####.#
+#E....#
+#.#####
+#######";
+        let (start, end, walls) = Solver::parse_input(maze.to_string()).unwrap();
+
+        let cheats = find_cheats(start, end, &walls, 2).unwrap();
+        let savings: Vec<_> = cheats.iter().map(|cheat| cheat.saving).collect();
+        assert_eq!(savings, vec![8, 6, 4, 2]);
+        assert_eq!(cheats[0].start, start);
+        assert_eq!(cheats[0].end, end);
     }
 }
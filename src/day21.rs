@@ -1,231 +1,293 @@
+use crate::a_star;
 use crate::common::{Direction, Position};
-use failure::Error;
-use itertools::{Either, Itertools};
+use failure::{err_msg, Error};
+use itertools::Itertools;
 use lazy_static::lazy_static;
 use std::collections::HashMap;
+use std::fmt::Debug;
 use std::hash::Hash;
+use std::iter::repeat_n;
 use std::str::FromStr;
 
-#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
-enum Moves {
-    North,
-    NorthEast,
-    EastNorth,
-    East,
-    EastSouth,
-    SouthEast,
-    South,
-    SouthWest,
-    WestSouth,
-    West,
-    WestNorth,
-    NorthWest,
-}
-
-impl Moves {
-    fn all() -> impl Iterator<Item = Moves> {
-        use Moves::*;
-
-        [
-            North, NorthEast, EastNorth, East, EastSouth, SouthEast, South, SouthWest, WestSouth,
-            West, WestNorth, NorthWest,
-        ]
-        .into_iter()
-    }
-
-    fn components(self) -> impl Iterator<Item = Direction> {
-        use Direction::*;
-        match self {
-            Moves::North => Either::Left([North].into_iter()),
-            Moves::NorthEast => Either::Right([North, East].into_iter()),
-            Moves::EastNorth => Either::Right([East, North].into_iter()),
-            Moves::East => Either::Left([East].into_iter()),
-            Moves::EastSouth => Either::Right([East, South].into_iter()),
-            Moves::SouthEast => Either::Right([South, East].into_iter()),
-            Moves::South => Either::Left([South].into_iter()),
-            Moves::SouthWest => Either::Right([South, West].into_iter()),
-            Moves::WestSouth => Either::Right([West, South].into_iter()),
-            Moves::West => Either::Left([West].into_iter()),
-            Moves::WestNorth => Either::Right([West, North].into_iter()),
-            Moves::NorthWest => Either::Right([North, West].into_iter()),
+const NUMERIC_LAYOUT: &str = "789\n456\n123\n 0A";
+
+const DIRECTIONAL_LAYOUT: &str = " ^A\n<v>";
+
+#[derive(Debug, Clone)]
+struct Keypad {
+    positions: HashMap<char, Position>,
+    buttons: HashMap<Position, char>,
+}
+
+impl FromStr for Keypad {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut positions = HashMap::new();
+
+        for (y, line) in s.lines().enumerate() {
+            for (x, c) in line.chars().enumerate() {
+                if !c.is_whitespace() && positions.insert(c, (x, y).into()).is_some() {
+                    return Err(err_msg(format!("Duplicate button {}", c)));
+                }
+            }
         }
-    }
 
-    fn for_direction(dir: Direction) -> impl Iterator<Item = Self> {
-        use Moves::*;
-        match dir {
-            Direction::North => Either::Left([North].into_iter()),
-            Direction::NorthEast => Either::Right([NorthEast, EastNorth].into_iter()),
-            Direction::East => Either::Left([East].into_iter()),
-            Direction::SouthEast => Either::Right([SouthEast, EastSouth].into_iter()),
-            Direction::South => Either::Left([South].into_iter()),
-            Direction::SouthWest => Either::Right([SouthWest, WestSouth].into_iter()),
-            Direction::West => Either::Left([West].into_iter()),
-            Direction::NorthWest => Either::Right([NorthWest, WestNorth].into_iter()),
+        if !positions.contains_key(&'A') {
+            return Err(err_msg("Keypad has no A button"));
         }
+
+        let buttons = positions.iter().map(|(&c, &p)| (p, c)).collect();
+        Ok(Keypad { positions, buttons })
     }
 }
 
-lazy_static! {
-    static ref NUMERIC_POSITIONS: HashMap<char, Position> = {
-        let mut positions = HashMap::new();
-        positions.insert('7', (0i64, 0).into());
-        positions.insert('8', (1i64, 0).into());
-        positions.insert('9', (2i64, 0).into());
-        positions.insert('4', (0i64, 1).into());
-        positions.insert('5', (1i64, 1).into());
-        positions.insert('6', (2i64, 1).into());
-        positions.insert('1', (0i64, 2).into());
-        positions.insert('2', (1i64, 2).into());
-        positions.insert('3', (2i64, 2).into());
-        positions.insert('0', (1i64, 3).into());
-        positions.insert('A', (2i64, 3).into());
-        positions
-    };
-    static ref NUMERIC_BUTTONS: HashMap<Position, char> =
-        NUMERIC_POSITIONS.iter().map(|(&c, &p)| (p, c)).collect();
-    static ref DIRECTIONAL_POSITIONS: HashMap<char, Position> = {
-        let mut positions = HashMap::new();
-        positions.insert('^', (1i64, 0).into());
-        positions.insert('A', (2i64, 0).into());
-        positions.insert('<', (0i64, 1).into());
-        positions.insert('v', (1i64, 1).into());
-        positions.insert('>', (2i64, 1).into());
-        positions
-    };
-    static ref DIRECTIONAL_BUTTONS: HashMap<Position, char> = {
-        DIRECTIONAL_POSITIONS
-            .iter()
-            .map(|(&c, &p)| (p, c))
-            .collect()
-    };
-}
+impl Keypad {
+    fn is_directional(&self) -> bool {
+        Direction::cardinal().all(|dir| self.positions.contains_key(&dir.as_char()))
+    }
 
-#[allow(unused)]
-fn get_output(presses: &str, buttons: &HashMap<Position, char>, start: Position) -> String {
-    let mut output = String::new();
+    fn get_output(&self, presses: &str) -> Result<String, Error> {
+        let mut output = String::new();
 
-    let mut pos = start;
+        let mut pos = self.positions[&'A'];
 
-    for press in presses.chars() {
-        if press == 'A' {
-            output.push(*buttons.get(&pos).unwrap());
-        } else {
-            let dir = Direction::try_from(press).unwrap();
-            pos = pos.step(dir);
+        for press in presses.chars() {
+            if press == 'A' {
+                output.push(self.buttons[&pos]);
+            } else {
+                pos = pos.step(Direction::try_from(press)?);
+                if !self.buttons.contains_key(&pos) {
+                    return Err(err_msg(format!("Robot arm moved over a gap at {}", pos)));
+                }
+            }
         }
+
+        Ok(output)
     }
+}
 
-    output
+lazy_static! {
+    static ref NUMERIC_KEYPAD: Keypad = NUMERIC_LAYOUT.parse().unwrap();
+    static ref DIRECTIONAL_KEYPAD: Keypad = DIRECTIONAL_LAYOUT.parse().unwrap();
 }
 
-#[allow(unused)]
-fn get_code(presses: &str) -> String {
-    let dir_start = *DIRECTIONAL_POSITIONS.get(&'A').unwrap();
-    let num_start = *NUMERIC_POSITIONS.get(&'A').unwrap();
-    let output = get_output(presses, &DIRECTIONAL_BUTTONS, dir_start);
-    let output = get_output(&output, &DIRECTIONAL_BUTTONS, dir_start);
-    get_output(&output, &NUMERIC_BUTTONS, num_start)
+#[derive(Clone)]
+struct ArmState<'a> {
+    position: Position,
+    last_press: char,
+    pressed: bool,
+    target: Position,
+    keypad: &'a Keypad,
+    costs: &'a HashMap<(char, char), u64>,
 }
 
-fn shortest_route(
-    from: Position,
-    to: Position,
-    cost: &HashMap<Moves, usize>,
-    buttons: &HashMap<Position, char>,
-) -> usize {
-    Moves::for_direction(from.direction_to(to))
-        .filter(|moves| {
-            if let &[first, _] = moves.components().collect::<Vec<_>>().as_slice() {
-                let corner = match first {
-                    Direction::North | Direction::South => Position { x: from.x, y: to.y },
-                    Direction::East | Direction::West => Position { x: to.x, y: from.y },
-                    _ => unreachable!(),
-                };
+impl Debug for ArmState<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "ArmState {{ position: {}, last_press: {}, pressed: {} }}",
+            self.position, self.last_press, self.pressed
+        )
+    }
+}
 
-                buttons.contains_key(&corner)
-            } else {
-                true
-            }
-        })
-        .map(|moves| cost.get(&moves).unwrap() + from.manhattan_distance_to(&to) as usize)
-        .min()
-        .unwrap()
+impl PartialEq for ArmState<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.position == other.position
+            && self.last_press == other.last_press
+            && self.pressed == other.pressed
+    }
 }
 
-fn min_cost_for_route(
-    route: impl Iterator<Item = Position>,
-    cost: &HashMap<Moves, usize>,
-    buttons: &HashMap<Position, char>,
-) -> usize {
-    route
-        .tuple_windows()
-        .map(|(from, to)| shortest_route(from, to, cost, buttons))
-        .sum()
+impl Eq for ArmState<'_> {}
+
+impl Hash for ArmState<'_> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.position.hash(state);
+        self.last_press.hash(state);
+        self.pressed.hash(state);
+    }
 }
 
-fn get_route<'a>(
-    presses: impl Iterator<Item = char> + 'a,
-    positions: &'a HashMap<char, Position>,
-) -> impl Iterator<Item = Position> + 'a {
-    [positions[&'A']]
-        .into_iter()
-        .chain(presses.map(|c| positions[&c]))
+impl a_star::State for ArmState<'_> {
+    fn is_end(&self) -> bool {
+        self.pressed
+    }
+
+    fn heuristic(&self) -> u64 {
+        0
+    }
+
+    fn successors(&self) -> Vec<(u64, Self)> {
+        if self.pressed {
+            return vec![];
+        }
+
+        let mut successors: Vec<_> = Direction::cardinal()
+            .map(|dir| (self.position.step(dir), dir.as_char()))
+            .filter(|(position, _)| self.keypad.buttons.contains_key(position))
+            .map(|(position, press)| {
+                (
+                    self.costs[&(self.last_press, press)],
+                    ArmState {
+                        position,
+                        last_press: press,
+                        ..self.clone()
+                    },
+                )
+            })
+            .collect();
+
+        if self.position == self.target {
+            successors.push((
+                self.costs[&(self.last_press, 'A')],
+                ArmState {
+                    last_press: 'A',
+                    pressed: true,
+                    ..self.clone()
+                },
+            ));
+        }
+
+        successors
+    }
 }
 
-fn min_cost_for_moves(
-    moves: Moves,
-    cost: &HashMap<Moves, usize>,
-    positions: &HashMap<char, Position>,
-    buttons: &HashMap<Position, char>,
-) -> usize {
-    let route = get_route(
-        moves.components().map(|d| d.as_char()).chain(['A']),
-        positions,
-    );
-    min_cost_for_route(route, cost, buttons)
+// The number of presses the human has to make to press each pair of buttons in turn on a
+// keypad, along with what has to be pressed on the keypad controlling it to do so.
+struct Level {
+    costs: HashMap<(char, char), u64>,
+    sequences: HashMap<(char, char), String>,
 }
 
-fn min_cost_for_code(
-    code: &str,
-    cost: &HashMap<Moves, usize>,
-    positions: &HashMap<char, Position>,
-    buttons: &HashMap<Position, char>,
-) -> usize {
-    let route = get_route(code.chars(), positions);
-    min_cost_for_route(route, cost, buttons) + code.len()
+impl Level {
+    fn pressed_directly(keypad: &Keypad) -> Self {
+        Level {
+            costs: keypad
+                .positions
+                .keys()
+                .cartesian_product(keypad.positions.keys())
+                .map(|(&from, &to)| ((from, to), 1))
+                .collect(),
+            sequences: HashMap::new(),
+        }
+    }
+
+    fn controlled_by(keypad: &Keypad, controller: &Level) -> Result<Self, Error> {
+        let mut costs = HashMap::new();
+        let mut sequences = HashMap::new();
+
+        for ((&from, &start), (&to, &target)) in keypad
+            .positions
+            .iter()
+            .cartesian_product(keypad.positions.iter())
+        {
+            let solution = a_star::solve([ArmState {
+                position: start,
+                last_press: 'A',
+                pressed: false,
+                target,
+                keypad,
+                costs: &controller.costs,
+            }])
+            .map_err(|_| err_msg(format!("Can't reach {} from {}", to, from)))?;
+
+            costs.insert((from, to), solution.cost);
+            sequences.insert(
+                (from, to),
+                solution.route[1..]
+                    .iter()
+                    .map(|state| state.last_press)
+                    .collect(),
+            );
+        }
+
+        Ok(Level { costs, sequences })
+    }
 }
 
-fn update_costs(
-    costs: &HashMap<Moves, usize>,
-    positions: &HashMap<char, Position>,
-    buttons: &HashMap<Position, char>,
-) -> HashMap<Moves, usize> {
-    Moves::all()
-        .map(|moves| (moves, min_cost_for_moves(moves, costs, positions, buttons)))
-        .collect()
+// A chain of keypads, starting with the one pressed by the human, where each keypad
+// controls the robot pressing the next one.
+struct KeypadChain {
+    keypads: Vec<Keypad>,
+    levels: Vec<Level>,
 }
 
-fn shortest_path_len(code: &str, intermediate_keypads: usize) -> usize {
-    let mut costs: HashMap<Moves, usize> = Moves::all().map(|m| (m, 0)).collect();
+impl KeypadChain {
+    fn new(keypads: Vec<Keypad>) -> Result<Self, Error> {
+        let Some((_, controllers)) = keypads.split_last() else {
+            return Err(err_msg("Keypad chain is empty"));
+        };
+
+        if !controllers.iter().all(Keypad::is_directional) {
+            return Err(err_msg("Keypads controlling robots must be directional"));
+        }
+
+        let mut levels = vec![Level::pressed_directly(&keypads[0])];
+        for keypad in keypads[1..].iter() {
+            levels.push(Level::controlled_by(keypad, levels.last().unwrap())?);
+        }
+
+        Ok(KeypadChain { keypads, levels })
+    }
+
+    fn robots(intermediate_keypads: usize) -> Self {
+        let keypads = repeat_n(DIRECTIONAL_KEYPAD.clone(), intermediate_keypads + 1)
+            .chain([NUMERIC_KEYPAD.clone()])
+            .collect();
+        KeypadChain::new(keypads).unwrap()
+    }
+
+    fn shortest_path_len(&self, code: &str) -> u64 {
+        let costs = &self.levels.last().unwrap().costs;
+        ['A']
+            .into_iter()
+            .chain(code.chars())
+            .tuple_windows()
+            .map(|(from, to)| costs[&(from, to)])
+            .sum()
+    }
+
+    fn shortest_path(&self, code: &str) -> String {
+        let mut presses = code.to_string();
 
-    for _ in 0..intermediate_keypads {
-        costs = update_costs(&costs, &DIRECTIONAL_POSITIONS, &DIRECTIONAL_BUTTONS);
+        for level in self.levels[1..].iter().rev() {
+            presses = ['A']
+                .into_iter()
+                .chain(presses.chars())
+                .tuple_windows()
+                .map(|(from, to)| level.sequences[&(from, to)].as_str())
+                .collect();
+        }
+
+        presses
+    }
+
+    fn get_code(&self, presses: &str) -> Result<String, Error> {
+        self.keypads[1..]
+            .iter()
+            .try_fold(presses.to_string(), |presses, keypad| {
+                keypad.get_output(&presses)
+            })
     }
+}
 
-    min_cost_for_code(code, &costs, &NUMERIC_POSITIONS, &NUMERIC_BUTTONS)
+fn check_shortest_paths(codes: &[String], chain: &KeypadChain) {
+    for code in codes {
+        let presses = chain.shortest_path(code);
+        assert_eq!(presses.len() as u64, chain.shortest_path_len(code));
+        assert_eq!(&chain.get_code(&presses).unwrap(), code);
+    }
 }
 
-fn get_complexity(code: &str, intermediate_keypads: usize) -> usize {
-    let sequence_len = shortest_path_len(code, intermediate_keypads);
-    sequence_len * usize::from_str(&code[..code.len() - 1]).unwrap()
+fn get_complexity(code: &str, chain: &KeypadChain) -> u64 {
+    let sequence_len = chain.shortest_path_len(code);
+    sequence_len * u64::from_str(&code[..code.len() - 1]).unwrap()
 }
 
-fn get_complexity_sum(codes: &[String], intermediate_keypads: usize) -> usize {
-    codes
-        .iter()
-        .map(|code| get_complexity(code, intermediate_keypads))
-        .sum()
+fn get_complexity_sum(codes: &[String], chain: &KeypadChain) -> u64 {
+    codes.iter().map(|code| get_complexity(code, chain)).sum()
 }
 
 pub struct Solver {}
@@ -242,8 +304,48 @@ impl super::Solver for Solver {
     }
 
     fn solve(codes: Self::Problem) -> (Option<String>, Option<String>) {
-        let part1 = get_complexity_sum(&codes, 2);
-        let part2 = get_complexity_sum(&codes, 25);
+        let chain = KeypadChain::robots(2);
+        check_shortest_paths(&codes, &chain);
+        let part1 = get_complexity_sum(&codes, &chain);
+        let part2 = get_complexity_sum(&codes, &KeypadChain::robots(25));
         (Some(part1.to_string()), Some(part2.to_string()))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{get_complexity_sum, Keypad, KeypadChain, DIRECTIONAL_LAYOUT, NUMERIC_LAYOUT};
+
+    #[test]
+    fn test_robots() {
+        let codes: Vec<_> = ["029A", "980A", "179A", "456A", "379A"]
+            .into_iter()
+            .map(str::to_string)
+            .collect();
+        let chain = KeypadChain::robots(2);
+
+        assert_eq!(chain.shortest_path_len("029A"), 68);
+        assert_eq!(get_complexity_sum(&codes, &chain), 126384);
+
+        for code in codes.iter() {
+            let presses = chain.shortest_path(code);
+            assert_eq!(presses.len() as u64, chain.shortest_path_len(code));
+            assert_eq!(&chain.get_code(&presses).unwrap(), code);
+        }
+    }
+
+    #[test]
+    fn test_custom_layouts() {
+        let ring: Keypad = "123\n8 4\n7A5\n 6".parse().unwrap();
+        let directional: Keypad = DIRECTIONAL_LAYOUT.parse().unwrap();
+        let chain = KeypadChain::new(vec![directional.clone(), directional, ring]).unwrap();
+
+        let presses = chain.shortest_path("2468A");
+        assert_eq!(presses.len() as u64, chain.shortest_path_len("2468A"));
+        assert_eq!(chain.get_code(&presses).unwrap(), "2468A");
+
+        let numeric: Keypad = NUMERIC_LAYOUT.parse().unwrap();
+        assert!(KeypadChain::new(vec![numeric.clone(), numeric]).is_err());
+        assert!(" ^\n<v>".parse::<Keypad>().is_err());
+    }
+}
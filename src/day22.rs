@@ -1,95 +1,365 @@
 use failure::{err_msg, Error};
 use itertools::Itertools;
-use std::{
-    collections::{HashMap, HashSet},
-    str::FromStr,
-};
+use lazy_static::lazy_static;
 
-#[derive(PartialEq, Eq, Hash, Clone, Copy)]
-struct DeltaSeq(u32);
+use crate::parallel;
+use std::{cmp::min, collections::HashMap, fmt::Display, ops::Range, str::FromStr};
+
+const NUM_DELTAS: u32 = 19;
+const NUM_DELTA_SEQS: usize = (NUM_DELTAS * NUM_DELTAS * NUM_DELTAS * NUM_DELTAS) as usize;
+const BATCH_SIZE: usize = 64;
+const NUM_CHUNKS: usize = 32;
+
+// Four price changes, each -9 to 9, packed into a dense index.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct DeltaSeq(u32);
+
+impl DeltaSeq {
+    fn push(self, delta: i8) -> Self {
+        DeltaSeq((self.0 * NUM_DELTAS + (delta + 9) as u32) % NUM_DELTA_SEQS as u32)
+    }
+
+    fn deltas(self) -> [i8; 4] {
+        let mut deltas = [0; 4];
+        let mut index = self.0;
+        for delta in deltas.iter_mut().rev() {
+            *delta = (index % NUM_DELTAS) as i8 - 9;
+            index /= NUM_DELTAS;
+        }
+        deltas
+    }
+}
 
 impl From<(i8, i8, i8, i8)> for DeltaSeq {
     fn from((a, b, c, d): (i8, i8, i8, i8)) -> Self {
-        DeltaSeq(
-            ((a as u8) as u32) << 24
-                | ((b as u8) as u32) << 16
-                | ((c as u8) as u32) << 8
-                | (d as u8) as u32,
-        )
+        DeltaSeq(0).push(a).push(b).push(c).push(d)
+    }
+}
+
+impl Display for DeltaSeq {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.deltas().iter().join(","))
+    }
+}
+
+fn next_secret(secret: u32) -> u32 {
+    let secret = (secret ^ (secret << 6)) & SECRET_MASK;
+    let secret = (secret ^ (secret >> 5)) & SECRET_MASK;
+    (secret ^ (secret << 11)) & SECRET_MASK
+}
+
+// Steps a batch of secrets together, which the compiler can vectorise.
+fn step_secrets(secrets: &mut [u32]) {
+    for secret in secrets.iter_mut() {
+        *secret = next_secret(*secret);
+    }
+}
+
+const SECRET_BITS: usize = 24;
+const SECRET_MASK: u32 = (1 << SECRET_BITS) - 1;
+
+// A linear map on 24-bit secrets over GF(2), stored as the image of each bit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct BitMatrix([u32; SECRET_BITS]);
+
+impl BitMatrix {
+    fn identity() -> Self {
+        BitMatrix(std::array::from_fn(|bit| 1 << bit))
+    }
+
+    // Each step of the generator only shifts and xors, so is linear.
+    fn step() -> Self {
+        BitMatrix(std::array::from_fn(|bit| next_secret(1 << bit)))
+    }
+
+    fn apply(&self, secret: u32) -> u32 {
+        self.0
+            .iter()
+            .enumerate()
+            .filter(|&(bit, _)| secret >> bit & 1 == 1)
+            .fold(0, |result, (_, column)| result ^ column)
+    }
+
+    // The map that applies other, then self.
+    fn compose(&self, other: &Self) -> Self {
+        BitMatrix(other.0.map(|column| self.apply(column)))
+    }
+
+    fn pow(&self, mut exponent: u64) -> Self {
+        let mut result = Self::identity();
+        let mut base = *self;
+
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = result.compose(&base);
+            }
+            base = base.compose(&base);
+            exponent >>= 1;
+        }
+
+        result
+    }
+
+    // Gauss-Jordan elimination, tracking which input bits make up each row.
+    fn inverse(&self) -> Option<Self> {
+        let mut rows: Vec<(u32, u32)> = (0..SECRET_BITS)
+            .map(|bit| {
+                let row = self
+                    .0
+                    .iter()
+                    .enumerate()
+                    .filter(|&(_, column)| column >> bit & 1 == 1)
+                    .fold(0, |row, (index, _)| row | 1 << index);
+                (row, 1 << bit)
+            })
+            .collect();
+
+        for bit in 0..SECRET_BITS {
+            let pivot = (bit..SECRET_BITS).find(|&row| rows[row].0 >> bit & 1 == 1)?;
+            rows.swap(bit, pivot);
+
+            let (pivot_row, pivot_inverse) = rows[bit];
+            for (index, row) in rows.iter_mut().enumerate() {
+                if index != bit && row.0 >> bit & 1 == 1 {
+                    row.0 ^= pivot_row;
+                    row.1 ^= pivot_inverse;
+                }
+            }
+        }
+
+        // Row i now says which output bits xor together to give input bit i.
+        Some(BitMatrix(std::array::from_fn(|output| {
+            rows.iter()
+                .enumerate()
+                .filter(|(_, &(_, inverse))| inverse >> output & 1 == 1)
+                .fold(0, |column, (input, _)| column | 1 << input)
+        })))
     }
 }
 
-fn mix(a: i64, b: i64) -> i64 {
-    a ^ b
+lazy_static! {
+    static ref STEP: BitMatrix = BitMatrix::step();
+    static ref INVERSE_STEP: BitMatrix = STEP.inverse().unwrap();
+}
+
+fn previous_secret(secret: u32) -> u32 {
+    INVERSE_STEP.apply(secret)
+}
+
+fn jump_secret(secret: u32, steps: u64) -> u32 {
+    STEP.pow(steps).apply(secret)
 }
 
-fn prune(a: i64) -> i64 {
-    a % 16777216
+// How many steps until each secret comes round again.  Every secret is on a cycle as the
+// step can be undone, and if the step's order is 2^24 - 1 then each cycle's length
+// divides it, so can be found by dividing out its prime factors.
+fn cycle_lengths(secrets: &[u32]) -> Vec<u64> {
+    let order = SECRET_MASK as u64;
+    if STEP.pow(order) != BitMatrix::identity() {
+        return secrets
+            .iter()
+            .map(|&secret| {
+                let mut current = next_secret(secret);
+                let mut length = 1;
+                while current != secret {
+                    current = next_secret(current);
+                    length += 1;
+                }
+                length
+            })
+            .collect();
+    }
+
+    let mut powers = HashMap::new();
+    let mut jump = |secret, steps| {
+        powers
+            .entry(steps)
+            .or_insert_with(|| STEP.pow(steps))
+            .apply(secret)
+    };
+
+    secrets
+        .iter()
+        .map(|&secret| {
+            let mut length = order;
+            for prime in [3, 5, 7, 13, 17, 241] {
+                while length.is_multiple_of(prime) && jump(secret, length / prime) == secret {
+                    length /= prime;
+                }
+            }
+            length
+        })
+        .collect()
 }
 
 #[derive(Clone, Debug, Copy)]
-struct SecretNumberSequence(i64);
+struct SecretNumberSequence(u32);
 
 impl Iterator for SecretNumberSequence {
-    type Item = i64;
+    type Item = u32;
 
     fn next(&mut self) -> Option<Self::Item> {
         let val = self.0;
-        self.0 = prune(mix(self.0, self.0 << 6));
-        self.0 = prune(mix(self.0, self.0 >> 5));
-        self.0 = prune(mix(self.0, self.0 << 11));
-
+        self.0 = next_secret(self.0);
         Some(val)
     }
+
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        self.0 = jump_secret(self.0, n as u64);
+        self.next()
+    }
 }
 
-fn price_seq(num: i64) -> impl Iterator<Item = i8> {
-    SecretNumberSequence(num).map(|n| (n % 10) as i8)
+impl SecretNumberSequence {
+    // Steps backwards, undoing the last call to next and returning the same secret.
+    fn previous(&mut self) -> u32 {
+        self.0 = previous_secret(self.0);
+        self.0
+    }
 }
 
-fn find_secret_number_sum(numbers: &[i64], index: usize) -> i64 {
+fn find_secret_number_sum(numbers: &[u32], index: usize) -> u64 {
+    let jump = STEP.pow(index as u64);
     numbers
         .iter()
-        .map(|&num| SecretNumberSequence(num).nth(index).unwrap())
+        .map(|&number| u64::from(jump.apply(number)))
         .sum()
 }
 
-fn update_delta_seq_prices(num: i64, count: usize, prices: &mut HashMap<DeltaSeq, i64>) {
-    let mut seen = HashSet::new();
+pub struct Market<'a> {
+    numbers: &'a [u32],
+    num_prices: usize,
+}
+
+impl<'a> Market<'a> {
+    fn new(numbers: &'a [u32], num_prices: usize) -> Self {
+        Market {
+            numbers,
+            num_prices,
+        }
+    }
+
+    // The price the buyer sells at, the first time the sequence of changes appears.
+    fn price_for(&self, buyer: usize, delta_seq: DeltaSeq) -> Option<i64> {
+        let prices = SecretNumberSequence(self.numbers[buyer])
+            .take(self.num_prices)
+            .map(|secret| (secret % 10) as i8);
+
+        prices
+            .tuple_windows()
+            .scan(DeltaSeq(0), |seq, (previous, price)| {
+                *seq = seq.push(price - previous);
+                Some(*seq)
+            })
+            .zip(SecretNumberSequence(self.numbers[buyer]).skip(1))
+            .skip(3)
+            .find(|&(seq, _)| seq == delta_seq)
+            .map(|(_, secret)| (secret % 10) as i64)
+    }
+
+    fn total_for(&self, delta_seq: DeltaSeq) -> i64 {
+        (0..self.numbers.len())
+            .filter_map(|buyer| self.price_for(buyer, delta_seq))
+            .sum()
+    }
+
+    // The total bananas for every sequence of changes, over a range of buyers.
+    fn accumulate(&self, buyers: Range<usize>) -> Vec<i64> {
+        let mut totals = vec![0; NUM_DELTA_SEQS];
+        let mut last_buyer = vec![usize::MAX; NUM_DELTA_SEQS];
 
-    let delta_seqs = price_seq(num)
-        .take(count)
-        .tuple_windows()
-        .map(|(p1, p2)| p2 - p1)
-        .tuple_windows()
-        .map(|seq: (i8, i8, i8, i8)| seq.into());
+        for start in buyers.clone().step_by(BATCH_SIZE) {
+            let batch = start..min(start + BATCH_SIZE, buyers.end);
 
-    for (delta_seq, price) in delta_seqs.zip(price_seq(num).skip(4)) {
-        if !seen.contains(&delta_seq) {
-            seen.insert(delta_seq);
-            *prices.entry(delta_seq).or_default() += price as i64;
+            // Generate the batch's prices together, one row per step.
+            let mut secrets = self.numbers[batch.clone()].to_vec();
+            let mut prices = Vec::with_capacity(self.num_prices * secrets.len());
+            for _ in 0..self.num_prices {
+                prices.extend(secrets.iter().map(|secret| (secret % 10) as i8));
+                step_secrets(&mut secrets);
+            }
+
+            for (lane, buyer) in batch.enumerate() {
+                let mut seq = DeltaSeq(0);
+                let mut buyer_prices = prices.iter().skip(lane).step_by(secrets.len());
+                let mut previous = *buyer_prices.next().unwrap();
+
+                for (step, &price) in buyer_prices.enumerate() {
+                    seq = seq.push(price - previous);
+                    previous = price;
+
+                    let index = seq.0 as usize;
+                    if step >= 3 && last_buyer[index] != buyer {
+                        last_buyer[index] = buyer;
+                        totals[index] += price as i64;
+                    }
+                }
+            }
+        }
+
+        totals
+    }
+
+    // The sequence of changes that gets the most bananas, and how many it gets.
+    fn best_sequence(&self) -> (DeltaSeq, i64) {
+        let chunk_size = self.numbers.len().div_ceil(NUM_CHUNKS).max(1);
+        let chunks: Vec<_> = (0..self.numbers.len())
+            .step_by(chunk_size)
+            .map(|start| start..min(start + chunk_size, self.numbers.len()))
+            .collect();
+
+        let mut totals = vec![0; NUM_DELTA_SEQS];
+        for chunk_totals in parallel::map(&chunks, |chunk| self.accumulate(chunk.clone())) {
+            for (total, chunk_total) in totals.iter_mut().zip(chunk_totals) {
+                *total += chunk_total;
+            }
         }
+
+        let (index, &total) = totals
+            .iter()
+            .enumerate()
+            .rev()
+            .max_by_key(|&(_, total)| total)
+            .unwrap();
+        (DeltaSeq(index as u32), total)
     }
 }
 
-fn max_num_bananas(numbers: &[i64], max_numbers: usize) -> i64 {
-    let mut delta_seq_prices = HashMap::new();
+// Each buyer's cycle and the secret they must have had before their first one, then the
+// best sequence of changes.
+pub fn analyze_input(data: String) -> Result<String, Error> {
+    let numbers = <Solver as super::Solver>::parse_input(data)?;
+    let lengths = cycle_lengths(&numbers);
 
-    for &num in numbers {
-        update_delta_seq_prices(num, max_numbers + 1, &mut delta_seq_prices);
+    let mut output = String::new();
+    for (&number, length) in numbers.iter().zip(&lengths) {
+        let mut sequence = SecretNumberSequence(number);
+        output.push_str(&format!(
+            "{}: repeats every {} steps, after {}\n",
+            number,
+            length,
+            sequence.previous()
+        ));
     }
 
-    *delta_seq_prices.values().max().unwrap()
+    let (delta_seq, total) = Market::new(&numbers, 2001).best_sequence();
+    output.push_str(&format!(
+        "Secrets repeat every {} steps\nBest sequence of changes: {} for {} bananas",
+        lengths.into_iter().unique().sorted().join(", "),
+        delta_seq,
+        total
+    ));
+    Ok(output)
 }
 
 pub struct Solver {}
 
 impl super::Solver for Solver {
-    type Problem = Box<[i64]>;
+    type Problem = Box<[u32]>;
 
     fn parse_input(data: String) -> Result<Self::Problem, Error> {
         data.lines()
-            .map(i64::from_str)
+            .map(u32::from_str)
             .collect::<Result<Vec<_>, _>>()
             .map(Vec::into_boxed_slice)
             .map_err(|err| err_msg(format!("Failed to parse input: {}", err)))
@@ -97,7 +367,93 @@ impl super::Solver for Solver {
 
     fn solve(numbers: Self::Problem) -> (Option<String>, Option<String>) {
         let part1 = find_secret_number_sum(&numbers, 2000);
-        let part2 = max_num_bananas(&numbers, 2000);
+
+        let market = Market::new(&numbers, 2001);
+        let (delta_seq, part2) = market.best_sequence();
+        debug_assert_eq!(market.total_for(delta_seq), part2);
+
         (Some(part1.to_string()), Some(part2.to_string()))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{
+        cycle_lengths, find_secret_number_sum, jump_secret, next_secret, previous_secret,
+        BitMatrix, DeltaSeq, Market, SecretNumberSequence, STEP,
+    };
+
+    #[test]
+    fn test_secret_numbers() {
+        assert_eq!(find_secret_number_sum(&[1, 10, 100, 2024], 2000), 37327623);
+    }
+
+    #[test]
+    fn test_jump_ahead() {
+        for secret in [1, 123, 2024, 16777215] {
+            for steps in [0, 1, 2, 10, 1999, 2000, 4321] {
+                let mut sequence = SecretNumberSequence(secret);
+                let brute_force = (0..steps).fold(secret, |secret, _| next_secret(secret));
+                assert_eq!(jump_secret(secret, steps as u64), brute_force);
+                assert_eq!(sequence.nth(steps), Some(brute_force));
+            }
+        }
+
+        assert_eq!(STEP.pow(5), STEP.compose(&STEP.pow(4)));
+        assert_eq!(STEP.pow(0), BitMatrix::identity());
+    }
+
+    #[test]
+    fn test_inverse() {
+        let inverse = STEP.inverse().unwrap();
+        assert_eq!(inverse.compose(&STEP), BitMatrix::identity());
+
+        let secrets: Vec<_> = SecretNumberSequence(123).take(10).collect();
+        for pair in secrets.windows(2) {
+            assert_eq!(previous_secret(pair[1]), pair[0]);
+        }
+
+        let mut sequence = SecretNumberSequence(123);
+        sequence.nth(9);
+        let backwards: Vec<_> = (0..10).map(|_| sequence.previous()).collect();
+        assert_eq!(backwards, secrets.iter().rev().copied().collect::<Vec<_>>());
+        assert_eq!(sequence.next(), Some(123));
+
+        assert_eq!(BitMatrix([1; 24]).inverse(), None);
+    }
+
+    #[test]
+    fn test_cycle_length() {
+        let lengths = cycle_lengths(&[0, 123]);
+        assert_eq!(lengths[0], 1);
+
+        let length = lengths[1];
+        assert_eq!(jump_secret(123, length), 123);
+
+        let mut secret = next_secret(123);
+        let mut brute_force = 1;
+        while secret != 123 {
+            secret = next_secret(secret);
+            brute_force += 1;
+        }
+        assert_eq!(length, brute_force);
+    }
+
+    #[test]
+    fn test_market() {
+        let numbers = [1, 2, 3, 2024];
+        let market = Market::new(&numbers, 2001);
+
+        let (delta_seq, total) = market.best_sequence();
+        assert_eq!(delta_seq.deltas(), [-2, 1, -1, 3]);
+        assert_eq!(delta_seq.to_string(), "-2,1,-1,3");
+        assert_eq!(total, 23);
+
+        assert_eq!(market.price_for(0, delta_seq), Some(7));
+        assert_eq!(market.price_for(2, delta_seq), None);
+        assert_eq!(market.total_for(delta_seq), 23);
+
+        let market = Market::new(&[123], 10);
+        assert_eq!(market.price_for(0, DeltaSeq::from((-1, -1, 0, 2))), Some(6));
+    }
+}
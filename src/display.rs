@@ -0,0 +1,295 @@
+use std::{
+    fmt::Display,
+    io::{stdin, stdout, BufRead, BufReader, Write},
+    sync::mpsc::{channel, Receiver, RecvTimeoutError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use ansi_term::{Colour, Style};
+use failure::Error;
+
+use crate::common::Position;
+
+const CLEAR_SCREEN: &str = "\x1b[2J\x1b[H";
+const MIN_DELAY: Duration = Duration::from_millis(1);
+const MAX_DELAY: Duration = Duration::from_secs(5);
+
+const HELP: &str = "p: pause/resume, s: step, +/-: speed, q: quit (then Enter)";
+
+#[derive(Clone)]
+pub struct Frame {
+    width: usize,
+    height: usize,
+    cells: Vec<(char, Style)>,
+    caption: Option<String>,
+}
+
+impl Frame {
+    pub fn new(size: Position) -> Self {
+        let width = size.x.max(0) as usize;
+        let height = size.y.max(0) as usize;
+        Frame {
+            width,
+            height,
+            cells: vec![('.', Colour::White.dimmed()); width * height],
+            caption: None,
+        }
+    }
+
+    pub fn enclosing<'a>(positions: impl IntoIterator<Item = &'a Position>) -> Self {
+        let size = positions
+            .into_iter()
+            .fold(Position::origin(), |size, pos| Position {
+                x: size.x.max(pos.x + 1),
+                y: size.y.max(pos.y + 1),
+            });
+        Frame::new(size)
+    }
+
+    pub fn caption(mut self, caption: impl Into<String>) -> Self {
+        self.caption = Some(caption.into());
+        self
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn title(&self) -> Option<&str> {
+        self.caption.as_deref()
+    }
+
+    pub fn cells(&self) -> impl Iterator<Item = (Position, char, Style)> + '_ {
+        self.cells
+            .iter()
+            .enumerate()
+            .map(|(index, &(symbol, style))| {
+                let position = (index % self.width.max(1), index / self.width.max(1)).into();
+                (position, symbol, style)
+            })
+    }
+
+    pub fn set(&mut self, position: Position, symbol: char, style: Style) {
+        if (0..self.width as i64).contains(&position.x)
+            && (0..self.height as i64).contains(&position.y)
+        {
+            self.cells[position.y as usize * self.width + position.x as usize] = (symbol, style);
+        }
+    }
+
+    pub fn draw<'a>(
+        &mut self,
+        positions: impl IntoIterator<Item = &'a Position>,
+        symbol: char,
+        style: Style,
+    ) {
+        for &position in positions {
+            self.set(position, symbol, style);
+        }
+    }
+
+    pub fn draw_grid<'a, T: 'a>(
+        &mut self,
+        grid: impl IntoIterator<Item = (&'a Position, &'a T)>,
+        symbol: impl Fn(&T) -> (char, Style),
+    ) {
+        for (&position, value) in grid {
+            let (symbol, style) = symbol(value);
+            self.set(position, symbol, style);
+        }
+    }
+}
+
+impl Display for Frame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(caption) = &self.caption {
+            writeln!(f, "{}", caption)?;
+        }
+
+        for row in self.cells.chunks(self.width.max(1)) {
+            for (symbol, style) in row {
+                write!(f, "{}", style.paint(symbol.to_string()))?;
+            }
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Control {
+    TogglePause,
+    Step,
+    Faster,
+    Slower,
+    Quit,
+}
+
+impl Control {
+    fn parse(command: &str) -> Option<Self> {
+        match command {
+            "p" | "pause" => Some(Control::TogglePause),
+            "s" | "step" => Some(Control::Step),
+            "+" | "faster" => Some(Control::Faster),
+            "-" | "slower" => Some(Control::Slower),
+            "q" | "quit" => Some(Control::Quit),
+            _ => None,
+        }
+    }
+}
+
+fn read_controls(input: impl BufRead + Send + 'static) -> Receiver<Control> {
+    let (sender, receiver) = channel();
+
+    thread::spawn(move || {
+        for line in input.lines() {
+            let Ok(line) = line else {
+                break;
+            };
+
+            for control in line.split_whitespace().filter_map(Control::parse) {
+                if sender.send(control).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+
+    receiver
+}
+
+pub struct Player {
+    delay: Duration,
+    paused: bool,
+}
+
+impl Player {
+    pub fn new(frames_per_second: u32) -> Self {
+        Player {
+            delay: Duration::from_secs(1) / frames_per_second.max(1),
+            paused: false,
+        }
+    }
+
+    pub fn play(&mut self, frames: impl IntoIterator<Item = Frame>) -> Result<(), Error> {
+        let controls = read_controls(BufReader::new(stdin()));
+        self.play_with(frames, &controls, &mut stdout())
+    }
+
+    // Returns false if the viewer asked to quit.
+    fn wait(&mut self, controls: &Receiver<Control>) -> bool {
+        let deadline = Instant::now() + self.delay;
+
+        loop {
+            let control = if self.paused {
+                match controls.recv() {
+                    Ok(control) => control,
+                    Err(_) => {
+                        self.paused = false;
+                        continue;
+                    }
+                }
+            } else {
+                match controls.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                    Ok(control) => control,
+                    Err(RecvTimeoutError::Timeout) => return true,
+                    Err(RecvTimeoutError::Disconnected) => {
+                        thread::sleep(deadline.saturating_duration_since(Instant::now()));
+                        return true;
+                    }
+                }
+            };
+
+            match control {
+                Control::TogglePause => {
+                    self.paused = !self.paused;
+                    if !self.paused {
+                        return true;
+                    }
+                }
+                Control::Step => {
+                    self.paused = true;
+                    return true;
+                }
+                Control::Faster => self.delay = (self.delay / 2).max(MIN_DELAY),
+                Control::Slower => self.delay = (self.delay * 2).min(MAX_DELAY),
+                Control::Quit => return false,
+            }
+        }
+    }
+
+    fn play_with(
+        &mut self,
+        frames: impl IntoIterator<Item = Frame>,
+        controls: &Receiver<Control>,
+        output: &mut impl Write,
+    ) -> Result<(), Error> {
+        for (index, frame) in frames.into_iter().enumerate() {
+            write!(output, "{}{}", CLEAR_SCREEN, frame)?;
+            writeln!(
+                output,
+                "Frame {} ({}ms{}) {}",
+                index,
+                self.delay.as_millis(),
+                if self.paused { ", paused" } else { "" },
+                HELP
+            )?;
+            output.flush()?;
+
+            if !self.wait(controls) {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{sync::mpsc::channel, time::Duration};
+
+    use ansi_term::Style;
+
+    use super::{Control, Frame, Player};
+    use crate::common::Position;
+
+    #[test]
+    fn test_frame() {
+        let mut frame = Frame::new(Position { x: 3, y: 2 }).caption("test");
+        frame.draw(
+            &[Position { x: 1, y: 0 }, Position { x: 5, y: 5 }],
+            '#',
+            Style::new(),
+        );
+        frame.set(Position { x: 2, y: 1 }, '@', Style::new());
+
+        let plain: Vec<_> = frame.cells.iter().map(|(symbol, _)| *symbol).collect();
+        assert_eq!(plain.iter().collect::<String>(), ".#...@");
+        assert!(frame.to_string().starts_with("test\n"));
+    }
+
+    #[test]
+    fn test_player() {
+        let (sender, receiver) = channel();
+        for control in [Control::Step, Control::Slower, Control::Step, Control::Quit] {
+            sender.send(control).unwrap();
+        }
+
+        let frames = (0..10).map(|_| Frame::new(Position { x: 1, y: 1 }));
+        let mut player = Player::new(1000);
+        let mut output = vec![];
+        player.play_with(frames, &receiver, &mut output).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output.matches("Frame ").count(), 3);
+        assert!(player.paused);
+        assert_eq!(player.delay, Duration::from_millis(2));
+    }
+}
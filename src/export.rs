@@ -0,0 +1,378 @@
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use ansi_term::{Colour, Style};
+use failure::{err_msg, Error};
+
+use crate::{
+    common::{Direction, Position},
+    display::Frame,
+};
+
+const CELL_SIZE: usize = 8;
+const BACKGROUND: [u8; 3] = [17, 17, 17];
+const FRAME_DELAY_MS: u16 = 100;
+pub const DEFAULT_FRAMES: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Png,
+    Svg,
+    Gif,
+}
+
+impl Format {
+    pub fn from_path(path: &Path) -> Result<Self, Error> {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("png") => Ok(Format::Png),
+            Some("svg") => Ok(Format::Svg),
+            Some("gif") => Ok(Format::Gif),
+            _ => Err(err_msg(format!(
+                "Can't tell image format of {}, expected .png, .svg or .gif",
+                path.display()
+            ))),
+        }
+    }
+
+    pub fn is_animated(self) -> bool {
+        self == Format::Gif
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Shape {
+    Empty,
+    Block,
+    Marker,
+    Arrow(Direction),
+}
+
+impl Shape {
+    fn for_symbol(symbol: char) -> Self {
+        match symbol {
+            '.' | ' ' => Shape::Empty,
+            '#' => Shape::Block,
+            _ => Direction::try_from(symbol)
+                .map(Shape::Arrow)
+                .unwrap_or(Shape::Marker),
+        }
+    }
+
+    // Whether the pixel at (x, y) within a cell is covered by the shape.
+    fn covers(self, x: usize, y: usize) -> bool {
+        let last = CELL_SIZE - 1;
+        match self {
+            Shape::Empty => false,
+            Shape::Block => true,
+            Shape::Marker => (1..last).contains(&x) && (1..last).contains(&y),
+            Shape::Arrow(direction) => {
+                let (along, across) = match direction {
+                    Direction::North => (y, x),
+                    Direction::South => (last - y, x),
+                    Direction::West => (x, y),
+                    _ => (last - x, y),
+                };
+                across
+                    .abs_diff(last / 2)
+                    .min(across.abs_diff(CELL_SIZE / 2))
+                    * 2
+                    <= along
+            }
+        }
+    }
+
+    fn svg(self, position: Position, fill: &str) -> Option<String> {
+        let (x, y) = (
+            position.x as usize * CELL_SIZE,
+            position.y as usize * CELL_SIZE,
+        );
+        let size = CELL_SIZE;
+        let half = size / 2;
+
+        match self {
+            Shape::Empty => None,
+            Shape::Block => Some(format!(
+                r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}"/>"#,
+                x, y, size, size, fill
+            )),
+            Shape::Marker => Some(format!(
+                r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}"/>"#,
+                x + 1,
+                y + 1,
+                size - 2,
+                size - 2,
+                fill
+            )),
+            Shape::Arrow(direction) => {
+                let points = match direction {
+                    Direction::North => [(half, 0), (size, size), (0, size)],
+                    Direction::South => [(half, size), (0, 0), (size, 0)],
+                    Direction::West => [(0, half), (size, 0), (size, size)],
+                    _ => [(size, half), (0, size), (0, 0)],
+                };
+                Some(format!(
+                    r#"<polygon points="{}" fill="{}"/>"#,
+                    points
+                        .iter()
+                        .map(|(px, py)| format!("{},{}", x + px, y + py))
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                    fill
+                ))
+            }
+        }
+    }
+}
+
+fn basic_colour(index: u8) -> [u8; 3] {
+    match index % 8 {
+        0 => [0, 0, 0],
+        1 => [205, 49, 49],
+        2 => [13, 188, 121],
+        3 => [229, 229, 16],
+        4 => [36, 114, 200],
+        5 => [188, 63, 188],
+        6 => [17, 168, 205],
+        _ => [229, 229, 229],
+    }
+}
+
+fn colour(style: &Style) -> [u8; 3] {
+    let rgb = match style.foreground.unwrap_or(Colour::White) {
+        Colour::Black => basic_colour(0),
+        Colour::Red => basic_colour(1),
+        Colour::Green => basic_colour(2),
+        Colour::Yellow => basic_colour(3),
+        Colour::Blue => basic_colour(4),
+        Colour::Purple => basic_colour(5),
+        Colour::Cyan => basic_colour(6),
+        Colour::White => basic_colour(7),
+        Colour::Fixed(index @ 0..=15) => basic_colour(index),
+        Colour::Fixed(index @ 16..=231) => {
+            let level = |value: u8| if value == 0 { 0 } else { 55 + 40 * value };
+            let index = index - 16;
+            [level(index / 36), level(index / 6 % 6), level(index % 6)]
+        }
+        Colour::Fixed(index) => [8 + 10 * (index - 232); 3],
+        Colour::RGB(r, g, b) => [r, g, b],
+    };
+
+    if style.is_dimmed {
+        rgb.map(|value| value / 2)
+    } else {
+        rgb
+    }
+}
+
+fn hex(rgb: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", rgb[0], rgb[1], rgb[2])
+}
+
+fn rasterize(frame: &Frame) -> (usize, usize, Vec<[u8; 3]>) {
+    let width = frame.width() * CELL_SIZE;
+    let height = frame.height() * CELL_SIZE;
+    let mut pixels = vec![BACKGROUND; width * height];
+
+    for (position, symbol, style) in frame.cells() {
+        let shape = Shape::for_symbol(symbol);
+        if shape == Shape::Empty {
+            continue;
+        }
+
+        let rgb = colour(&style);
+        for y in 0..CELL_SIZE {
+            for x in 0..CELL_SIZE {
+                if shape.covers(x, y) {
+                    let px = position.x as usize * CELL_SIZE + x;
+                    let py = position.y as usize * CELL_SIZE + y;
+                    pixels[py * width + px] = rgb;
+                }
+            }
+        }
+    }
+
+    (width, height, pixels)
+}
+
+// Text for an SVG text node or attribute value.
+pub fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+pub fn to_svg(frame: &Frame) -> String {
+    let width = frame.width() * CELL_SIZE;
+    let height = frame.height() * CELL_SIZE;
+
+    let mut svg = String::new();
+    writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="0 0 {} {}">"#,
+        width, height, width, height
+    )
+    .unwrap();
+    if let Some(title) = frame.title() {
+        writeln!(svg, "<title>{}</title>", escape_xml(title)).unwrap();
+    }
+    writeln!(
+        svg,
+        r#"<rect width="100%" height="100%" fill="{}"/>"#,
+        hex(BACKGROUND)
+    )
+    .unwrap();
+
+    for (position, symbol, style) in frame.cells() {
+        if let Some(element) = Shape::for_symbol(symbol).svg(position, &hex(colour(&style))) {
+            writeln!(svg, "{}", element).unwrap();
+        }
+    }
+
+    writeln!(svg, "</svg>").unwrap();
+    svg
+}
+
+fn write_png(frame: &Frame, output: impl Write) -> Result<(), Error> {
+    let (width, height, pixels) = rasterize(frame);
+
+    let mut encoder = png::Encoder::new(output, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&pixels.concat())?;
+    Ok(())
+}
+
+fn gif_frame(frame: &Frame) -> Result<gif::Frame<'static>, Error> {
+    let (width, height, pixels) = rasterize(frame);
+
+    let mut palette: HashMap<[u8; 3], u8> = HashMap::new();
+    let mut indices = Vec::with_capacity(pixels.len());
+    for rgb in pixels {
+        let next = palette.len();
+        if next == 256 && !palette.contains_key(&rgb) {
+            return Err(err_msg("Too many colours for a GIF frame"));
+        }
+        indices.push(*palette.entry(rgb).or_insert(next as u8));
+    }
+
+    let mut colours = vec![0; palette.len() * 3];
+    for (rgb, index) in palette {
+        colours[index as usize * 3..][..3].copy_from_slice(&rgb);
+    }
+
+    let mut frame = gif::Frame::from_palette_pixels(
+        u16::try_from(width)?,
+        u16::try_from(height)?,
+        indices,
+        colours,
+        None,
+    );
+    frame.delay = FRAME_DELAY_MS / 10;
+    Ok(frame)
+}
+
+fn write_gif(frames: impl Iterator<Item = Frame>, output: impl Write) -> Result<(), Error> {
+    let mut frames = frames.peekable();
+    let first = frames.peek().ok_or_else(|| err_msg("No frames to write"))?;
+    let width = u16::try_from(first.width() * CELL_SIZE)?;
+    let height = u16::try_from(first.height() * CELL_SIZE)?;
+
+    let mut encoder = gif::Encoder::new(output, width, height, &[])?;
+    encoder.set_repeat(gif::Repeat::Infinite)?;
+
+    for frame in frames {
+        encoder.write_frame(&gif_frame(&frame)?)?;
+    }
+
+    Ok(())
+}
+
+// Writes up to max_frames frames to an animated image, or just the last frame to a still image.
+pub fn save(
+    path: &Path,
+    frames: impl IntoIterator<Item = Frame>,
+    max_frames: usize,
+) -> Result<(), Error> {
+    let format = Format::from_path(path)?;
+    let mut output = BufWriter::new(File::create(path)?);
+
+    if format.is_animated() {
+        write_gif(frames.into_iter().take(max_frames), &mut output)?;
+    } else {
+        let frame = frames
+            .into_iter()
+            .last()
+            .ok_or_else(|| err_msg("No frame to write"))?;
+
+        match format {
+            Format::Png => write_png(&frame, &mut output)?,
+            _ => output.write_all(to_svg(&frame).as_bytes())?,
+        }
+    }
+
+    output.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use ansi_term::Colour;
+
+    use super::{rasterize, to_svg, write_gif, write_png, Shape, BACKGROUND, CELL_SIZE};
+    use crate::{
+        common::{Direction, Position},
+        display::Frame,
+    };
+
+    fn frame() -> Frame {
+        let mut frame = Frame::new(Position { x: 3, y: 1 }).caption("Route");
+        frame.set(Position { x: 0, y: 0 }, '#', Colour::Red.normal());
+        frame.set(Position { x: 1, y: 0 }, '>', Colour::Green.bold());
+        frame
+    }
+
+    #[test]
+    fn test_shapes() {
+        let arrow = Shape::Arrow(Direction::East);
+        assert!(arrow.covers(CELL_SIZE - 1, CELL_SIZE / 2));
+        assert!(arrow.covers(0, 0));
+        assert!(!arrow.covers(CELL_SIZE - 1, 0));
+        assert!(Shape::Marker.covers(CELL_SIZE / 2, CELL_SIZE / 2));
+        assert!(!Shape::Marker.covers(0, 0));
+    }
+
+    #[test]
+    fn test_rasterize() {
+        let (width, height, pixels) = rasterize(&frame());
+        assert_eq!((width, height), (3 * CELL_SIZE, CELL_SIZE));
+        assert_eq!(pixels[0], [205, 49, 49]);
+        assert_eq!(pixels[width - 1], BACKGROUND);
+    }
+
+    #[test]
+    fn test_formats() {
+        let svg = to_svg(&frame());
+        assert!(svg.contains("<title>Route</title>"));
+
+        let caption = frame().caption("Move <, 3 & 4");
+        assert!(to_svg(&caption).contains("<title>Move &lt;, 3 &amp; 4</title>"));
+        assert_eq!(svg.matches("<rect").count(), 2);
+        assert_eq!(svg.matches("<polygon").count(), 1);
+
+        let mut png = vec![];
+        write_png(&frame(), &mut png).unwrap();
+        assert!(png.starts_with(b"\x89PNG"));
+
+        let mut gif = vec![];
+        write_gif([frame(), frame()].into_iter(), &mut gif).unwrap();
+        assert!(gif.starts_with(b"GIF89a"));
+    }
+}
@@ -0,0 +1,227 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write,
+};
+
+#[derive(Debug, Clone, Default)]
+pub struct ConnectionMap<'a> {
+    connections: HashMap<&'a str, HashSet<&'a str>>,
+}
+
+impl<'a> ConnectionMap<'a> {
+    pub fn add_connection(&mut self, a: &'a str, b: &'a str) {
+        self.connections.entry(a).or_default().insert(b);
+        self.connections.entry(b).or_default().insert(a);
+    }
+
+    pub fn computers(&self) -> impl Iterator<Item = &'a str> + '_ {
+        self.connections.keys().copied()
+    }
+
+    fn sorted_computers(&self) -> Vec<&'a str> {
+        let mut computers: Vec<_> = self.computers().collect();
+        computers.sort();
+        computers
+    }
+
+    fn neighbours(&self, name: &str) -> &HashSet<&'a str> {
+        &self.connections[name]
+    }
+
+    pub fn has_connection(&self, a: &str, b: &str) -> bool {
+        self.connections
+            .get(a)
+            .is_some_and(|conns| conns.contains(b))
+    }
+
+    // Every maximal clique, each sorted by name, using Bron-Kerbosch with pivoting.
+    pub fn maximal_cliques(&self) -> Vec<Vec<&'a str>> {
+        let mut cliques = vec![];
+        self.bron_kerbosch(
+            &mut vec![],
+            self.computers().collect(),
+            HashSet::new(),
+            &mut cliques,
+        );
+        cliques.sort();
+        cliques
+    }
+
+    fn bron_kerbosch(
+        &self,
+        clique: &mut Vec<&'a str>,
+        mut candidates: HashSet<&'a str>,
+        mut excluded: HashSet<&'a str>,
+        cliques: &mut Vec<Vec<&'a str>>,
+    ) {
+        // Any maximal clique contains the pivot or one of its non-neighbours, so only
+        // those need to be tried.
+        let Some(pivot) = candidates
+            .union(&excluded)
+            .max_by_key(|&&name| self.neighbours(name).intersection(&candidates).count())
+        else {
+            let mut found = clique.clone();
+            found.sort();
+            cliques.push(found);
+            return;
+        };
+
+        let to_try: Vec<_> = candidates
+            .difference(self.neighbours(pivot))
+            .copied()
+            .collect();
+
+        for name in to_try {
+            let neighbours = self.neighbours(name);
+            clique.push(name);
+            self.bron_kerbosch(
+                clique,
+                candidates.intersection(neighbours).copied().collect(),
+                excluded.intersection(neighbours).copied().collect(),
+                cliques,
+            );
+            clique.pop();
+
+            candidates.remove(name);
+            excluded.insert(name);
+        }
+    }
+
+    // The largest clique, picking the first by name if there's a tie.
+    pub fn largest_clique(&self) -> Vec<&'a str> {
+        self.maximal_cliques()
+            .into_iter()
+            .rev()
+            .max_by_key(|clique| clique.len())
+            .unwrap_or_default()
+    }
+
+    // Every clique of exactly k computers where at least one matches the predicate.
+    pub fn k_cliques(
+        &self,
+        k: usize,
+        predicate: Option<&dyn Fn(&str) -> bool>,
+    ) -> Vec<Vec<&'a str>> {
+        let mut cliques = vec![];
+        let mut stack: Vec<Vec<&'a str>> = vec![vec![]];
+
+        // Build up each clique in name order, so that each is only found once.
+        let computers = self.sorted_computers();
+        while let Some(clique) = stack.pop() {
+            if clique.len() == k {
+                if predicate.is_none_or(|predicate| clique.iter().any(|name| predicate(name))) {
+                    cliques.push(clique);
+                }
+                continue;
+            }
+
+            let start = clique
+                .last()
+                .map_or(0, |last| computers.partition_point(|name| name <= last));
+            for &name in &computers[start..] {
+                if clique.iter().all(|other| self.has_connection(other, name)) {
+                    let mut extended = clique.clone();
+                    extended.push(name);
+                    stack.push(extended);
+                }
+            }
+        }
+
+        cliques.sort();
+        cliques
+    }
+
+    // The network in Graphviz's DOT language, with the computers and connections in the
+    // given clique highlighted.
+    pub fn to_dot(&self, highlight: &[&str]) -> String {
+        let highlighted = |name: &str| highlight.contains(&name);
+
+        let mut dot = String::new();
+        writeln!(dot, "graph network {{").unwrap();
+        writeln!(dot, "    node [shape=circle];").unwrap();
+
+        for name in self.sorted_computers() {
+            if highlighted(name) {
+                writeln!(dot, "    {} [style=filled, fillcolor=gold];", name).unwrap();
+            } else {
+                writeln!(dot, "    {};", name).unwrap();
+            }
+        }
+
+        for a in self.sorted_computers() {
+            let mut neighbours: Vec<_> = self.neighbours(a).iter().filter(|&&b| a < b).collect();
+            neighbours.sort();
+            for b in neighbours {
+                if highlighted(a) && highlighted(b) {
+                    writeln!(dot, "    {} -- {} [color=red, penwidth=2];", a, b).unwrap();
+                } else {
+                    writeln!(dot, "    {} -- {};", a, b).unwrap();
+                }
+            }
+        }
+
+        writeln!(dot, "}}").unwrap();
+        dot
+    }
+}
+
+impl<'a> FromIterator<&'a (String, String)> for ConnectionMap<'a> {
+    fn from_iter<T: IntoIterator<Item = &'a (String, String)>>(connections: T) -> Self {
+        let mut connection_map = ConnectionMap::default();
+        for (a, b) in connections {
+            connection_map.add_connection(a, b);
+        }
+        connection_map
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ConnectionMap;
+
+    fn square_with_diagonal() -> Vec<(String, String)> {
+        [
+            ("a", "b"),
+            ("b", "c"),
+            ("c", "d"),
+            ("d", "a"),
+            ("a", "c"),
+            ("e", "f"),
+        ]
+        .into_iter()
+        .map(|(a, b)| (a.to_string(), b.to_string()))
+        .collect()
+    }
+
+    #[test]
+    fn test_cliques() {
+        let connections = square_with_diagonal();
+        let map: ConnectionMap = connections.iter().collect();
+
+        assert_eq!(
+            map.maximal_cliques(),
+            vec![vec!["a", "b", "c"], vec!["a", "c", "d"], vec!["e", "f"]]
+        );
+        assert_eq!(map.largest_clique(), vec!["a", "b", "c"]);
+
+        assert_eq!(map.k_cliques(2, None).len(), 6);
+        assert_eq!(
+            map.k_cliques(3, Some(&|name| name == "d")),
+            vec![vec!["a", "c", "d"]]
+        );
+        assert!(map.k_cliques(4, None).is_empty());
+    }
+
+    #[test]
+    fn test_dot() {
+        let connections = square_with_diagonal();
+        let map: ConnectionMap = connections.iter().collect();
+
+        let dot = map.to_dot(&map.largest_clique());
+        assert!(dot.starts_with("graph network {"));
+        assert!(dot.contains("    b [style=filled, fillcolor=gold];"));
+        assert!(dot.contains("    a -- b [color=red, penwidth=2];"));
+        assert!(dot.contains("    a -- d;"));
+        assert_eq!(dot.matches(" -- ").count(), 6);
+    }
+}
@@ -0,0 +1,355 @@
+#![allow(unused)]
+
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+};
+
+use ansi_term::Colour;
+use failure::{err_msg, Error};
+
+use crate::{common::Position, display::Frame};
+
+// Which steps a trail can take: each one changes the height by `climb`, give or take
+// `tolerance`, on the way from a trailhead at `start` to a summit at `end`.  A negative
+// climb makes trails go downhill.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rules {
+    pub start: u8,
+    pub end: u8,
+    pub climb: i8,
+    pub tolerance: u8,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Rules {
+            start: 0,
+            end: 9,
+            climb: 1,
+            tolerance: 0,
+        }
+    }
+}
+
+impl Rules {
+    // The same trails, walked from the summit back down to the trailhead.
+    pub fn descending(self) -> Self {
+        Rules {
+            start: self.end,
+            end: self.start,
+            climb: -self.climb,
+            tolerance: self.tolerance,
+        }
+    }
+
+    // Whether every step goes the same way, so a trail can never come back round.
+    fn is_monotonic(&self) -> bool {
+        self.climb.unsigned_abs() > self.tolerance
+    }
+
+    fn allows(&self, from: u8, to: u8) -> bool {
+        (to as i16 - from as i16 - self.climb as i16).unsigned_abs() <= self.tolerance as u16
+    }
+}
+
+// A grid of single digit heights, where '.' is impassable.
+#[derive(Debug, Clone)]
+pub struct Map {
+    heights: Vec<Vec<Option<u8>>>,
+    rules: Rules,
+}
+
+impl FromStr for Map {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.lines()
+            .map(|line| {
+                line.chars()
+                    .map(|c| match c {
+                        '.' => Ok(None),
+                        _ => c
+                            .to_digit(10)
+                            .map(|d| Some(d as u8))
+                            .ok_or_else(|| err_msg(format!("Invalid digit: {}", c))),
+                    })
+                    .collect()
+            })
+            .collect::<Result<_, _>>()
+            .map(|heights| Map {
+                heights,
+                rules: Rules::default(),
+            })
+    }
+}
+
+impl Map {
+    pub fn with_rules(self, rules: Rules) -> Self {
+        Map { rules, ..self }
+    }
+
+    pub fn all_positions(&self) -> impl Iterator<Item = Position> + '_ {
+        self.heights
+            .iter()
+            .enumerate()
+            .flat_map(|(y, row)| (0..row.len()).map(move |x| (x, y).into()))
+    }
+
+    pub fn height_at(&self, position: Position) -> Option<u8> {
+        if position.x < 0 || position.y < 0 {
+            return None;
+        }
+
+        self.heights
+            .get(position.y as usize)
+            .and_then(|row| row.get(position.x as usize).cloned())
+            .flatten()
+    }
+
+    pub fn trailheads(&self) -> impl Iterator<Item = Position> + '_ {
+        self.all_positions()
+            .filter(|&pos| self.height_at(pos) == Some(self.rules.start))
+    }
+
+    fn steps_from(&self, position: Position) -> impl Iterator<Item = Position> + '_ {
+        let height = self.height_at(position);
+        position.adjacent().filter(move |&next_pos| {
+            height
+                .zip(self.height_at(next_pos))
+                .is_some_and(|(from, to)| self.rules.allows(from, to))
+        })
+    }
+
+    // Every trail from the position to a summit, one at a time.  There can be very many,
+    // so use the score or rating to count them.
+    pub fn trails(&self, trailhead: Position) -> Trails<'_> {
+        let stack = if self.height_at(trailhead) == Some(self.rules.start) {
+            vec![vec![trailhead]]
+        } else {
+            vec![]
+        };
+        Trails { map: self, stack }
+    }
+
+    // The number of summits reachable from the position.
+    pub fn trailhead_score(&self, position: Position) -> usize {
+        if self.height_at(position) != Some(self.rules.start) {
+            return 0;
+        }
+
+        if self.rules.is_monotonic() {
+            self.summits_from(position, &mut HashMap::new()).len()
+        } else {
+            self.reachable_summits(position).len()
+        }
+    }
+
+    // The number of distinct trails from the position.
+    pub fn trailhead_rating(&self, position: Position) -> usize {
+        if self.height_at(position) != Some(self.rules.start) {
+            return 0;
+        }
+
+        // Counting the trails that don't go round in circles has no shortcut.
+        if self.rules.is_monotonic() {
+            self.ratings_from(position, &mut HashMap::new())
+        } else {
+            self.trails(position).count()
+        }
+    }
+
+    // The summits reachable from each position, built up from those of the next step.
+    fn summits_from(
+        &self,
+        position: Position,
+        summits: &mut HashMap<Position, HashSet<Position>>,
+    ) -> HashSet<Position> {
+        if let Some(reachable) = summits.get(&position) {
+            return reachable.clone();
+        }
+
+        let reachable = if self.height_at(position) == Some(self.rules.end) {
+            HashSet::from([position])
+        } else {
+            let next: Vec<_> = self.steps_from(position).collect();
+            next.into_iter()
+                .flat_map(|next_pos| self.summits_from(next_pos, summits))
+                .collect()
+        };
+
+        summits.insert(position, reachable.clone());
+        reachable
+    }
+
+    fn ratings_from(&self, position: Position, ratings: &mut HashMap<Position, usize>) -> usize {
+        if let Some(&rating) = ratings.get(&position) {
+            return rating;
+        }
+
+        let rating = if self.height_at(position) == Some(self.rules.end) {
+            1
+        } else {
+            let next: Vec<_> = self.steps_from(position).collect();
+            next.into_iter()
+                .map(|next_pos| self.ratings_from(next_pos, ratings))
+                .sum()
+        };
+
+        ratings.insert(position, rating);
+        rating
+    }
+
+    // A search that copes with trails that can go round in circles.
+    fn reachable_summits(&self, position: Position) -> HashSet<Position> {
+        let mut seen = HashSet::from([position]);
+        let mut to_visit = vec![position];
+        let mut summits = HashSet::new();
+
+        while let Some(pos) = to_visit.pop() {
+            if self.height_at(pos) == Some(self.rules.end) {
+                summits.insert(pos);
+                continue;
+            }
+            for next_pos in self.steps_from(pos) {
+                if seen.insert(next_pos) {
+                    to_visit.push(next_pos);
+                }
+            }
+        }
+
+        summits
+    }
+
+    // The map with a trail picked out on it.
+    pub fn highlight(&self, trail: &[Position]) -> Frame {
+        let mut frame = Frame::enclosing(&self.all_positions().collect::<Vec<_>>());
+        for position in self.all_positions() {
+            if let Some(height) = self.height_at(position) {
+                let digit = char::from_digit(height as u32, 10).unwrap();
+                frame.set(position, digit, Colour::White.dimmed());
+            }
+        }
+
+        for &position in trail {
+            let digit = char::from_digit(self.height_at(position).unwrap() as u32, 10).unwrap();
+            frame.set(position, digit, Colour::Green.bold());
+        }
+        if let (Some(&first), Some(&last)) = (trail.first(), trail.last()) {
+            frame.set(first, 'S', Colour::Blue.bold());
+            frame.set(last, 'E', Colour::Blue.bold());
+        }
+
+        frame
+    }
+}
+
+// Walks trails depth first, so only the trails still being explored are kept.
+pub struct Trails<'a> {
+    map: &'a Map,
+    stack: Vec<Vec<Position>>,
+}
+
+impl Iterator for Trails<'_> {
+    type Item = Vec<Position>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(trail) = self.stack.pop() {
+            let &last = trail.last().unwrap();
+            if self.map.height_at(last) == Some(self.map.rules.end) {
+                return Some(trail);
+            }
+
+            // With a tolerance a trail could go round in circles, so it never revisits
+            // a position.
+            for next_pos in self.map.steps_from(last) {
+                if !trail.contains(&next_pos) {
+                    let mut extended = trail.clone();
+                    extended.push(next_pos);
+                    self.stack.push(extended);
+                }
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Map, Rules};
+    use crate::common::Position;
+
+    const EXAMPLE: &str = "89010123
+78121874
+87430965
+96549874
+45678903
+32019012
+01329801
+10456732";
+
+    #[test]
+    fn test_trails() {
+        let map: Map = EXAMPLE.parse().unwrap();
+        let trailhead = Position { x: 2, y: 0 };
+
+        assert_eq!(map.trailhead_score(trailhead), 5);
+        assert_eq!(map.trailhead_rating(trailhead), 20);
+        assert!(map.trails(trailhead).all(|trail| trail.len() == 10));
+        assert_eq!(map.trails(Position { x: 0, y: 0 }).count(), 0);
+
+        let trail = map.trails(trailhead).next().unwrap();
+        let frame = map.highlight(&trail);
+        assert_eq!(
+            frame
+                .cells()
+                .filter(|&(_, symbol, _)| symbol == 'S')
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_rules() {
+        let map: Map = "0123\n.894\n.765".parse().unwrap();
+        assert_eq!(map.trails(Position::origin()).count(), 1);
+
+        let descending = map.clone().with_rules(Rules::default().descending());
+        let summit = Position { x: 2, y: 1 };
+        assert_eq!(descending.trailheads().collect::<Vec<_>>(), vec![summit]);
+        assert_eq!(descending.trailhead_score(summit), 1);
+
+        // Steps of two, or one to three with a tolerance.
+        let rules = Rules {
+            start: 0,
+            end: 8,
+            climb: 2,
+            tolerance: 0,
+        };
+        let strict = "0246\n2468".parse::<Map>().unwrap().with_rules(rules);
+        assert_eq!(strict.trailhead_score(Position::origin()), 1);
+        assert_eq!(strict.trailhead_rating(Position::origin()), 4);
+
+        let loose = "02468\n13579".parse::<Map>().unwrap().with_rules(Rules {
+            end: 9,
+            tolerance: 1,
+            ..rules
+        });
+        assert_eq!(loose.trailhead_score(Position::origin()), 1);
+        assert_eq!(loose.trailhead_rating(Position::origin()), 5);
+
+        // Level steps are allowed, so trails could go round in circles.
+        let level = "0112\n1112".parse::<Map>().unwrap().with_rules(Rules {
+            end: 2,
+            climb: 0,
+            tolerance: 1,
+            ..rules
+        });
+        assert_eq!(level.trailhead_score(Position::origin()), 2);
+        assert_eq!(
+            level.trailhead_rating(Position::origin()),
+            level.trails(Position::origin()).count()
+        );
+    }
+}
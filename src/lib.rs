@@ -2,6 +2,9 @@ use aocf::Aoc;
 use failure::Error;
 use std::fs::read_to_string;
 use std::path::Path;
+
+pub use export::DEFAULT_FRAMES;
+pub use parallel::set_threads;
 use std::str::FromStr;
 
 mod a_star;
@@ -30,8 +33,16 @@ mod day21;
 mod day22;
 mod day23;
 mod day24;
+mod display;
 mod djikstra;
+mod export;
+mod graph;
+mod height_map;
+mod math;
+mod parallel;
 mod parsers;
+mod partial_order;
+mod regions;
 
 #[derive(Debug, Eq, PartialEq)]
 pub enum Part {
@@ -132,3 +143,106 @@ pub fn solve_day(day: u32, data: String, aoc: &mut Aoc, submit: Option<Part>) ->
         _ => Err(failure::err_msg(format!("Invalid day {}", day))),
     }
 }
+
+pub fn disassemble(day: u32, data: String, source: bool) -> Result<String, Error> {
+    match day {
+        17 => day17::disassemble_input(&data, source),
+        _ => Err(failure::err_msg(format!(
+            "No program to disassemble for day {}",
+            day
+        ))),
+    }
+}
+
+pub fn debug(day: u32, data: String) -> Result<(), Error> {
+    match day {
+        17 => day17::debug_input(&data),
+        _ => Err(failure::err_msg(format!(
+            "No program to debug for day {}",
+            day
+        ))),
+    }
+}
+
+pub fn assemble(day: u32, data: String) -> Result<String, Error> {
+    match day {
+        17 => day17::assemble_input(&data),
+        _ => Err(failure::err_msg(format!("No assembler for day {}", day))),
+    }
+}
+
+pub fn antinodes(
+    day: u32,
+    data: String,
+    frequencies: Option<&str>,
+    resonant: bool,
+) -> Result<String, Error> {
+    match day {
+        8 => day08::antinodes_input(data, frequencies, resonant),
+        _ => Err(failure::err_msg(format!("No antennas for day {}", day))),
+    }
+}
+
+pub fn arrange(day: u32, data: String, limit: usize) -> Result<String, Error> {
+    match day {
+        19 => day19::arrange_input(data, limit),
+        _ => Err(failure::err_msg(format!(
+            "No towels to arrange for day {}",
+            day
+        ))),
+    }
+}
+
+pub fn defrag(day: u32, data: String, strategy: &str) -> Result<String, Error> {
+    match day {
+        9 => day09::defrag_input(data, strategy),
+        _ => Err(failure::err_msg(format!(
+            "No disk to defragment for day {}",
+            day
+        ))),
+    }
+}
+
+pub fn cheats(day: u32, data: String, length: u64, min_saving: u64) -> Result<String, Error> {
+    match day {
+        20 => day20::cheats_input(data, length, min_saving),
+        _ => Err(failure::err_msg(format!(
+            "No cheats to find for day {}",
+            day
+        ))),
+    }
+}
+
+pub fn analyze(day: u32, data: String) -> Result<String, Error> {
+    match day {
+        11 => day11::analyze_input(data),
+        22 => day22::analyze_input(data),
+        _ => Err(failure::err_msg(format!("No analysis for day {}", day))),
+    }
+}
+
+pub fn visualize(day: u32, data: String) -> Result<(), Error> {
+    match day {
+        6 => day06::visualize_input(data),
+        10 => day10::visualize_input(data),
+        14 => day14::visualize_input(data),
+        15 => day15::visualize_input(data),
+        _ => Err(failure::err_msg(format!(
+            "No visualization for day {}",
+            day
+        ))),
+    }
+}
+
+pub fn export(day: u32, data: String, path: &Path, max_frames: usize) -> Result<(), Error> {
+    match day {
+        6 => day06::export_input(data, path, max_frames),
+        10 => day10::export_input(data, path, max_frames),
+        12 => day12::export_input(data, path),
+        14 => day14::export_input(data, path, max_frames),
+        15 => day15::export_input(data, path, max_frames),
+        16 => day16::export_input(data, path, max_frames),
+        23 => day23::export_input(data, path),
+        _ => Err(failure::err_msg(format!("No image export for day {}", day))),
+    }
+}
@@ -4,7 +4,10 @@ use failure::Error;
 use std::path::PathBuf;
 use structopt::StructOpt;
 
-use aoc2024::{read_input, solve_day, Part};
+use aoc2024::{
+    analyze, antinodes, arrange, assemble, cheats, debug, defrag, disassemble, export, read_input,
+    set_threads, solve_day, visualize, Part, DEFAULT_FRAMES,
+};
 
 #[derive(StructOpt, Debug)]
 struct Opt {
@@ -13,11 +16,79 @@ struct Opt {
 
     #[structopt(long)]
     submit: Option<Part>,
+
+    /// Play back the puzzle's simulation in the terminal
+    #[structopt(long)]
+    visualize: bool,
+
+    /// Number of threads to solve with, if built with the parallel feature
+    #[structopt(long)]
+    threads: Option<usize>,
+
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(StructOpt, Debug)]
+enum Command {
+    /// Disassemble the puzzle's program and describe its loops
+    Disasm {
+        /// Print source that the assembler accepts instead of the analysis
+        #[structopt(long)]
+        source: bool,
+    },
+    /// Assemble a program from mnemonics into comma-separated values
+    Asm,
+    /// Step through the puzzle's program in an interactive debugger
+    Debug,
+    /// Show where the antennas' antinodes are, and which frequencies cause them
+    Antinodes {
+        /// Only look at antennas with these frequencies
+        #[structopt(long)]
+        frequencies: Option<String>,
+        /// Include every point in line with two antennas
+        #[structopt(long)]
+        resonant: bool,
+    },
+    /// Show the shortest arrangements of towels for each pattern, and a few others
+    Arrange {
+        /// Maximum number of arrangements to list for each pattern
+        #[structopt(long, default_value = "5")]
+        limit: usize,
+    },
+    /// Show the disk before and after defragmenting it
+    Defrag {
+        /// One of block, file, best-fit or end
+        #[structopt(long, default_value = "file")]
+        strategy: String,
+    },
+    /// Show how many cheats save each amount of time
+    Cheats {
+        /// Maximum number of picoseconds a cheat can last
+        #[structopt(long, default_value = "2")]
+        length: u64,
+        /// Only list cheats that save at least this many picoseconds
+        #[structopt(long, default_value = "1")]
+        min_saving: u64,
+    },
+    /// Show how the puzzle's numbers repeat and which values matter most
+    Analyze,
+    /// Render the puzzle to a PNG or SVG image, an animated GIF, or a DOT graph
+    Export {
+        path: PathBuf,
+        /// Maximum number of frames to write to an animated GIF
+        #[structopt(long)]
+        frames: Option<usize>,
+    },
 }
 
 fn main() -> Result<(), Error> {
     let opt = Opt::from_args();
 
+    if let Some(threads) = opt.threads {
+        set_threads(threads)?;
+    }
+
     let mut aoc = Aoc::new()
         .parse_cli(false)
         .year(Some(2024))
@@ -27,7 +98,29 @@ fn main() -> Result<(), Error> {
     let data = read_input(opt.input, &mut aoc)
         .map_err(|err| failure::err_msg(format!("Failed to read input: {}", err)))?;
 
-    solve_day(opt.day, data, &mut aoc, opt.submit)?;
+    match opt.command {
+        Some(Command::Disasm { source }) => print!("{}", disassemble(opt.day, data, source)?),
+        Some(Command::Asm) => println!("{}", assemble(opt.day, data)?),
+        Some(Command::Debug) => debug(opt.day, data)?,
+        Some(Command::Antinodes {
+            frequencies,
+            resonant,
+        }) => print!(
+            "{}",
+            antinodes(opt.day, data, frequencies.as_deref(), resonant)?
+        ),
+        Some(Command::Arrange { limit }) => println!("{}", arrange(opt.day, data, limit)?),
+        Some(Command::Defrag { strategy }) => println!("{}", defrag(opt.day, data, &strategy)?),
+        Some(Command::Cheats { length, min_saving }) => {
+            println!("{}", cheats(opt.day, data, length, min_saving)?)
+        }
+        Some(Command::Analyze) => println!("{}", analyze(opt.day, data)?),
+        Some(Command::Export { path, frames }) => {
+            export(opt.day, data, &path, frames.unwrap_or(DEFAULT_FRAMES))?
+        }
+        None if opt.visualize => visualize(opt.day, data)?,
+        None => solve_day(opt.day, data, &mut aoc, opt.submit)?,
+    }
 
     Ok(())
 }
@@ -0,0 +1,200 @@
+#![allow(unused)]
+
+use num::{rational::Ratio, Zero};
+
+pub fn gcd(a: i64, b: i64) -> i64 {
+    ext_gcd(a, b).0
+}
+
+// Returns (g, x, y) such that a*x + b*y = g, where g is the non-negative gcd of a and b.
+pub fn ext_gcd(a: i64, b: i64) -> (i64, i64, i64) {
+    let (mut old_r, mut r) = (a, b);
+    let (mut old_x, mut x) = (1, 0);
+    let (mut old_y, mut y) = (0, 1);
+
+    while r != 0 {
+        let quotient = old_r / r;
+        (old_r, r) = (r, old_r - quotient * r);
+        (old_x, x) = (x, old_x - quotient * x);
+        (old_y, y) = (y, old_y - quotient * y);
+    }
+
+    if old_r < 0 {
+        (-old_r, -old_x, -old_y)
+    } else {
+        (old_r, old_x, old_y)
+    }
+}
+
+pub fn mod_inverse(a: i64, modulus: i64) -> Option<i64> {
+    if modulus <= 0 {
+        return None;
+    }
+
+    let (g, x, _) = ext_gcd(a.rem_euclid(modulus), modulus);
+    (g == 1).then(|| x.rem_euclid(modulus))
+}
+
+// Finds the smallest non-negative t with t = r (mod n) for each (r, n), returning t and
+// the period with which solutions repeat.  The moduli don't need to be coprime.
+pub fn crt(congruences: &[(i64, i64)]) -> Option<(i64, i64)> {
+    congruences
+        .iter()
+        .try_fold((0, 1), |(r1, n1), &(r2, n2)| crt_pair(r1, n1, r2, n2))
+}
+
+fn crt_pair(r1: i64, n1: i64, r2: i64, n2: i64) -> Option<(i64, i64)> {
+    if n2 <= 0 {
+        return None;
+    }
+
+    let g = gcd(n1, n2);
+    let diff = r2 - r1;
+    if diff % g != 0 {
+        return None;
+    }
+
+    // Solve r1 + k*n1 = r2 (mod n2) for k, after dividing through by the shared factor.
+    let modulus = n2 / g;
+    let inverse = mod_inverse(n1 / g, modulus)?;
+    let k = (diff / g) as i128 * inverse as i128 % modulus as i128;
+
+    let lcm = n1 as i128 * modulus as i128;
+    let t = (r1 as i128 + k * n1 as i128).rem_euclid(lcm);
+
+    Some((i64::try_from(t).ok()?, i64::try_from(lcm).ok()?))
+}
+
+// All integer solutions to a*x + b*y = c, as (x + k*step_x, y + k*step_y) for any k.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Diophantine {
+    pub x: i64,
+    pub y: i64,
+    pub step_x: i64,
+    pub step_y: i64,
+}
+
+impl Diophantine {
+    pub fn at(&self, k: i64) -> (i64, i64) {
+        (self.x + k * self.step_x, self.y + k * self.step_y)
+    }
+}
+
+pub fn solve_diophantine(a: i64, b: i64, c: i64) -> Option<Diophantine> {
+    if a == 0 && b == 0 {
+        return None;
+    }
+
+    let (g, x, y) = ext_gcd(a, b);
+    if c % g != 0 {
+        return None;
+    }
+
+    let scale = c / g;
+    Some(Diophantine {
+        x: x.checked_mul(scale)?,
+        y: y.checked_mul(scale)?,
+        step_x: b / g,
+        step_y: -a / g,
+    })
+}
+
+// Solves the square system matrix * x = rhs exactly, returning None unless there's a
+// unique solution.
+pub fn solve_linear_system(matrix: &[Vec<i64>], rhs: &[i64]) -> Option<Vec<Ratio<i128>>> {
+    let size = rhs.len();
+    let mut rows: Vec<Vec<Ratio<i128>>> = matrix
+        .iter()
+        .zip(rhs)
+        .map(|(row, &value)| {
+            assert_eq!(row.len(), size);
+            row.iter()
+                .chain([&value])
+                .map(|&v| Ratio::from_integer(v as i128))
+                .collect()
+        })
+        .collect();
+
+    for column in 0..size {
+        let pivot = (column..size).find(|&row| !rows[row][column].is_zero())?;
+        rows.swap(column, pivot);
+
+        let pivot_row = rows[column].clone();
+        for (index, row) in rows.iter_mut().enumerate() {
+            if index == column || row[column].is_zero() {
+                continue;
+            }
+
+            let factor = row[column] / pivot_row[column];
+            for (value, &pivot_value) in row.iter_mut().zip(&pivot_row) {
+                *value -= factor * pivot_value;
+            }
+        }
+    }
+
+    Some(
+        rows.iter()
+            .enumerate()
+            .map(|(i, row)| row[size] / row[i])
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use num::rational::Ratio;
+
+    use super::{crt, ext_gcd, mod_inverse, solve_diophantine, solve_linear_system};
+
+    #[test]
+    fn test_ext_gcd() {
+        for (a, b) in [(240, 46), (-12, 18), (7, 0), (0, -5), (101, 103)] {
+            let (g, x, y) = ext_gcd(a, b);
+            assert_eq!(a * x + b * y, g);
+            assert_eq!(g, num::integer::gcd(a, b));
+        }
+    }
+
+    #[test]
+    fn test_mod_inverse() {
+        assert_eq!(mod_inverse(3, 11), Some(4));
+        assert_eq!(mod_inverse(-3, 11), Some(7));
+        assert_eq!(mod_inverse(6, 9), None);
+        assert_eq!(mod_inverse(3, 0), None);
+        assert_eq!(mod_inverse(3, -11), None);
+    }
+
+    #[test]
+    fn test_crt() {
+        assert_eq!(crt(&[(2, 3), (3, 5), (2, 7)]), Some((23, 105)));
+        assert_eq!(crt(&[(3, 4), (5, 6)]), Some((11, 12)));
+        assert_eq!(crt(&[(1, 4), (2, 6)]), None);
+        assert_eq!(crt(&[(1, 4), (2, 0)]), None);
+        assert_eq!(crt(&[(1, -4)]), None);
+
+        let (t, period) = crt(&[(34, 101), (20, 103)]).unwrap();
+        assert_eq!((t % 101, t % 103, period), (34, 20, 10403));
+    }
+
+    #[test]
+    fn test_diophantine() {
+        let solution = solve_diophantine(94, 22, 8400).unwrap();
+        for k in -3..3 {
+            let (x, y) = solution.at(k);
+            assert_eq!(94 * x + 22 * y, 8400);
+        }
+        assert_eq!(solution.step_x, 11);
+        assert!(solve_diophantine(4, 6, 7).is_none());
+    }
+
+    #[test]
+    fn test_linear_system() {
+        let solution = solve_linear_system(&[vec![94, 22], vec![34, 67]], &[8400, 5400]).unwrap();
+        assert_eq!(solution, vec![Ratio::from(80), Ratio::from(40)]);
+
+        let solution = solve_linear_system(&[vec![0, 2], vec![3, 1]], &[1, 1]).unwrap();
+        assert_eq!(solution, vec![Ratio::new(1, 6), Ratio::new(1, 2)]);
+
+        assert!(solve_linear_system(&[vec![1, 2], vec![2, 4]], &[1, 2]).is_none());
+    }
+}
@@ -0,0 +1,116 @@
+use failure::{err_msg, Error};
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+// Applies f to each item, in parallel when built with the parallel feature.  The
+// results are always in the same order as the items.
+#[cfg(feature = "parallel")]
+pub fn map<T, R, F>(items: &[T], f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync + Send,
+{
+    items.par_iter().map(f).collect()
+}
+
+#[cfg(not(feature = "parallel"))]
+pub fn map<T, R, F>(items: &[T], f: F) -> Vec<R>
+where
+    F: Fn(&T) -> R,
+{
+    items.iter().map(f).collect()
+}
+
+pub fn count<T, F>(items: &[T], predicate: F) -> usize
+where
+    T: Sync,
+    F: Fn(&T) -> bool + Sync + Send,
+{
+    map(items, predicate)
+        .into_iter()
+        .filter(|&matched| matched)
+        .count()
+}
+
+#[cfg(feature = "parallel")]
+pub fn set_threads(threads: usize) -> Result<(), Error> {
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build_global()
+        .map_err(|err| err_msg(format!("Failed to start {} threads: {}", threads, err)))
+}
+
+#[cfg(not(feature = "parallel"))]
+pub fn set_threads(threads: usize) -> Result<(), Error> {
+    if threads == 1 {
+        Ok(())
+    } else {
+        Err(err_msg(
+            "Built without the parallel feature, so only 1 thread",
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{count, map};
+    use crate::{day07, day19, Solver};
+
+    fn answers<S: Solver>(data: &str) -> (Option<String>, Option<String>) {
+        S::solve(S::parse_input(data.to_string()).unwrap())
+    }
+
+    #[test]
+    fn test_matches_serial() {
+        let items: Vec<u64> = (0..10000).collect();
+        let square = |&item: &u64| item * item % 97;
+
+        assert_eq!(
+            map(&items, square),
+            items.iter().map(square).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            count(&items, |&item| item % 7 == 3),
+            items.iter().filter(|&&item| item % 7 == 3).count()
+        );
+    }
+
+    // Run with and without --features parallel; the answers mustn't depend on it.
+    #[test]
+    fn test_day_answers() {
+        let equations = "\
+190: 10 19
+3267: 81 40 27
+83: 17 5
+156: 15 6
+7290: 6 8 6 15
+161011: 16 10 13
+192: 17 8 14
+21037: 9 7 18 13
+292: 11 6 16 20
+";
+        assert_eq!(
+            answers::<day07::Solver>(equations),
+            (Some("3749".to_string()), Some("11387".to_string()))
+        );
+
+        let towels = "\
+r, wr, b, g, bwu, rb, gb, br
+
+brwrr
+bggr
+gbbr
+rrbgbr
+ubwu
+bwurrg
+brgr
+bbrgwb
+";
+        assert_eq!(
+            answers::<day19::Solver>(towels),
+            (Some("6".to_string()), Some("16".to_string()))
+        );
+    }
+}
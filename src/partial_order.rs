@@ -0,0 +1,185 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
+    fmt::Display,
+    hash::Hash,
+};
+
+use failure::{err_msg, Error};
+use itertools::Itertools;
+
+// A set of rules that one item must come before another.  Only rules between items
+// that are both present are considered when ordering or checking a sequence.
+#[derive(Debug, Clone)]
+pub struct PartialOrder<T> {
+    after: HashMap<T, HashSet<T>>,
+}
+
+impl<T: Copy + Eq + Hash + Ord + Display> PartialOrder<T> {
+    pub fn new(rules: impl IntoIterator<Item = (T, T)>) -> Self {
+        let mut after: HashMap<T, HashSet<T>> = HashMap::new();
+        for (first, second) in rules {
+            after.entry(first).or_default().insert(second);
+        }
+        PartialOrder { after }
+    }
+
+    fn successors<'a>(&'a self, item: T, items: &'a HashSet<T>) -> impl Iterator<Item = T> + 'a {
+        self.after
+            .get(&item)
+            .into_iter()
+            .flatten()
+            .copied()
+            .filter(move |next| items.contains(next))
+    }
+
+    // Every rule that the sequence breaks, as (first, second) where second comes first.
+    pub fn violations(&self, sequence: &[T]) -> Vec<(T, T)> {
+        let positions: HashMap<T, usize> = sequence
+            .iter()
+            .enumerate()
+            .map(|(index, &item)| (item, index))
+            .collect();
+        let positions = &positions;
+
+        sequence
+            .iter()
+            .enumerate()
+            .flat_map(|(index, &first)| {
+                self.after
+                    .get(&first)
+                    .into_iter()
+                    .flatten()
+                    .filter(move |second| positions.get(second).is_some_and(|&pos| pos < index))
+                    .map(move |&second| (first, second))
+            })
+            .sorted()
+            .collect()
+    }
+
+    pub fn is_ordered(&self, sequence: &[T]) -> bool {
+        self.violations(sequence).is_empty()
+    }
+
+    // A cycle of rules between the items, starting and ending with the same item.
+    pub fn find_cycle(&self, items: &[T]) -> Option<Vec<T>> {
+        let present: HashSet<T> = items.iter().copied().collect();
+        let mut finished = HashSet::new();
+
+        for &start in items {
+            if finished.contains(&start) {
+                continue;
+            }
+
+            // Depth first search, keeping the current path so a cycle can be read off it.
+            let mut path = vec![start];
+            let mut stack = vec![self.successors(start, &present).collect::<Vec<_>>()];
+
+            while let Some(next) = stack.last_mut() {
+                match next.pop() {
+                    Some(item) if finished.contains(&item) => {}
+                    Some(item) => {
+                        if let Some(index) = path.iter().position(|&other| other == item) {
+                            let mut cycle = path[index..].to_vec();
+                            cycle.push(item);
+                            return Some(cycle);
+                        }
+                        path.push(item);
+                        stack.push(self.successors(item, &present).collect());
+                    }
+                    None => {
+                        finished.insert(path.pop().unwrap());
+                        stack.pop();
+                    }
+                }
+            }
+        }
+
+        None
+    }
+
+    // Orders the items so that every rule between them holds, keeping items in their
+    // original order where the rules allow.  Repeated items end up next to each other.
+    pub fn sort(&self, items: &[T]) -> Result<Vec<T>, Error> {
+        let counts = items.iter().copied().counts();
+        let distinct: Vec<T> = items.iter().copied().unique().collect();
+        let present: HashSet<T> = distinct.iter().copied().collect();
+        let index: HashMap<T, usize> = distinct
+            .iter()
+            .enumerate()
+            .map(|(index, &item)| (item, index))
+            .collect();
+
+        let mut num_before: HashMap<T, usize> = distinct.iter().map(|&item| (item, 0)).collect();
+        for &item in &present {
+            for next in self.successors(item, &present) {
+                *num_before.get_mut(&next).unwrap() += 1;
+            }
+        }
+
+        let mut ready: BinaryHeap<_> = num_before
+            .iter()
+            .filter(|&(_, &count)| count == 0)
+            .map(|(item, _)| Reverse(index[item]))
+            .collect();
+
+        let mut sorted = vec![];
+        while let Some(Reverse(position)) = ready.pop() {
+            let item = distinct[position];
+            sorted.extend(std::iter::repeat_n(item, counts[&item]));
+
+            for next in self.successors(item, &present) {
+                let count = num_before.get_mut(&next).unwrap();
+                *count -= 1;
+                if *count == 0 {
+                    ready.push(Reverse(index[&next]));
+                }
+            }
+        }
+
+        if sorted.len() == items.len() {
+            Ok(sorted)
+        } else {
+            let cycle = self
+                .find_cycle(items)
+                .expect("Items that can't be sorted must contain a cycle");
+            Err(err_msg(format!(
+                "Rules form a cycle: {}",
+                cycle.iter().join(" -> ")
+            )))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::PartialOrder;
+
+    #[test]
+    fn test_sort() {
+        let order = PartialOrder::new([(1, 2), (2, 3), (4, 3), (5, 1)]);
+        assert_eq!(order.sort(&[3, 2, 1, 4]).unwrap(), vec![1, 2, 4, 3]);
+        assert_eq!(order.sort(&[4, 5, 3]).unwrap(), vec![4, 5, 3]);
+        assert!(order.is_ordered(&[5, 1, 4, 2, 3]));
+
+        assert_eq!(order.sort(&[3, 1, 3, 4, 1]).unwrap(), vec![1, 1, 4, 3, 3]);
+    }
+
+    #[test]
+    fn test_violations() {
+        let order = PartialOrder::new([(1, 2), (2, 3), (1, 3), (4, 3)]);
+        assert_eq!(order.violations(&[3, 2, 1]), vec![(1, 2), (1, 3), (2, 3)]);
+        assert_eq!(order.violations(&[1, 3, 2]), vec![(2, 3)]);
+    }
+
+    #[test]
+    fn test_cycles() {
+        let order = PartialOrder::new([(1, 2), (2, 3), (3, 1), (3, 4)]);
+        assert_eq!(order.find_cycle(&[4, 1, 2, 3]), Some(vec![1, 2, 3, 1]));
+        assert_eq!(order.find_cycle(&[1, 2, 4]), None);
+        assert!(order.sort(&[1, 2, 4]).is_ok());
+
+        let err = order.sort(&[4, 3, 2, 1]).unwrap_err();
+        assert!(err.to_string().contains("cycle"));
+    }
+}
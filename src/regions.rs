@@ -0,0 +1,368 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write,
+};
+
+use itertools::{iproduct, Itertools};
+
+use crate::{
+    common::{Direction, Position},
+    export::escape_xml,
+};
+
+const SCALE: i64 = 8;
+
+// A closed loop through the corners of plots, where plot (x, y) has corners (x, y) to
+// (x + 1, y + 1).  The region is always on the right, so outer boundaries go clockwise
+// and holes go anticlockwise.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Polygon {
+    corners: Vec<Position>,
+}
+
+impl Polygon {
+    fn new(mut corners: Vec<Position>) -> Self {
+        let (first, _) = corners
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, corner)| (corner.y, corner.x))
+            .unwrap();
+        corners.rotate_left(first);
+        Polygon { corners }
+    }
+
+    fn edges(&self) -> impl Iterator<Item = (&Position, &Position)> {
+        self.corners.iter().circular_tuple_windows()
+    }
+
+    pub fn sides(&self) -> usize {
+        self.corners.len()
+    }
+
+    // Twice the area, positive for clockwise polygons since y increases down the grid.
+    fn signed_double_area(&self) -> i64 {
+        self.edges().map(|(a, b)| a.x * b.y - b.x * a.y).sum()
+    }
+
+    pub fn is_hole(&self) -> bool {
+        self.signed_double_area() < 0
+    }
+
+    // Whether the centre of a plot is inside, by counting the edges crossed going left
+    // from it.
+    fn contains(&self, plot: Position) -> bool {
+        self.edges()
+            .filter(|(a, b)| {
+                a.x == b.x && a.x <= plot.x && a.y.min(b.y) <= plot.y && plot.y < a.y.max(b.y)
+            })
+            .count()
+            % 2
+            == 1
+    }
+
+    fn svg_path(&self) -> String {
+        let moves = self
+            .corners
+            .iter()
+            .map(|corner| format!("{} {}", corner.x * SCALE, corner.y * SCALE))
+            .join(" L ");
+        format!("M {} Z", moves)
+    }
+}
+
+// The smallest and largest plot positions in a region.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BoundingBox {
+    pub min: Position,
+    pub max: Position,
+}
+
+#[derive(Debug, Clone)]
+pub struct Region {
+    plant: char,
+    plots: HashSet<Position>,
+}
+
+impl Region {
+    pub fn contains(&self, position: Position) -> bool {
+        self.plots.contains(&position)
+    }
+
+    pub fn area(&self) -> usize {
+        self.plots.len()
+    }
+
+    pub fn perimeter(&self) -> usize {
+        iproduct!(self.plots.iter(), Direction::cardinal())
+            .filter(|&(pos, dir)| !self.contains(pos.step(dir)))
+            .count()
+    }
+
+    pub fn sides(&self) -> usize {
+        self.boundaries().iter().map(Polygon::sides).sum()
+    }
+
+    pub fn holes(&self) -> usize {
+        self.boundaries()
+            .iter()
+            .filter(|polygon| polygon.is_hole())
+            .count()
+    }
+
+    pub fn bounding_box(&self) -> BoundingBox {
+        let (min_x, max_x) = self
+            .plots
+            .iter()
+            .map(|pos| pos.x)
+            .minmax()
+            .into_option()
+            .unwrap();
+        let (min_y, max_y) = self
+            .plots
+            .iter()
+            .map(|pos| pos.y)
+            .minmax()
+            .into_option()
+            .unwrap();
+        BoundingBox {
+            min: (min_x, min_y).into(),
+            max: (max_x, max_y).into(),
+        }
+    }
+
+    // The outer boundary followed by any holes.  Where two plots only touch at a corner
+    // the boundary goes around each of them separately, so a gap that's open at a corner
+    // isn't a hole.
+    pub fn boundaries(&self) -> Vec<Polygon> {
+        // Each exposed side of a plot, as the corner it starts from and the direction
+        // along it that keeps the plot on the right.
+        let edges: HashSet<(Position, Direction)> =
+            iproduct!(self.plots.iter().copied(), Direction::cardinal())
+                .filter(|&(plot, dir)| !self.contains(plot.step(dir)))
+                .map(|(plot, dir)| {
+                    let corner = match dir {
+                        Direction::North => plot,
+                        Direction::East => plot.step(Direction::East),
+                        Direction::South => plot.step(Direction::SouthEast),
+                        _ => plot.step(Direction::South),
+                    };
+                    (corner, dir.turn_right())
+                })
+                .collect();
+
+        let mut visited = HashSet::new();
+        let mut polygons = vec![];
+
+        for &start in &edges {
+            if visited.contains(&start) {
+                continue;
+            }
+
+            let mut corners = vec![];
+            let mut edge = start;
+            loop {
+                visited.insert(edge);
+
+                // Turning right first keeps hugging the same plot.
+                let (corner, dir) = edge;
+                let end = corner.step(dir);
+                let next = [dir.turn_right(), dir, dir.turn_left()]
+                    .into_iter()
+                    .map(|next_dir| (end, next_dir))
+                    .find(|next| edges.contains(next))
+                    .unwrap();
+
+                if next.1 != dir {
+                    corners.push(end);
+                }
+
+                edge = next;
+                if edge == start {
+                    break;
+                }
+            }
+
+            polygons.push(Polygon::new(corners));
+        }
+
+        polygons.sort_by_key(|polygon| {
+            let first = polygon.corners[0];
+            (polygon.is_hole(), first.y, first.x)
+        });
+        polygons
+    }
+}
+
+// Splits the grid into regions of the same plant, in reading order of their first plot.
+pub fn find_regions(plots: &HashMap<Position, char>) -> Vec<Region> {
+    let mut seen = HashSet::new();
+    let mut regions = vec![];
+
+    for (&start, &plant) in plots.iter().sorted_by_key(|(pos, _)| (pos.y, pos.x)) {
+        if !seen.insert(start) {
+            continue;
+        }
+
+        let mut region = Region {
+            plant,
+            plots: HashSet::new(),
+        };
+        let mut to_visit = vec![start];
+        while let Some(pos) = to_visit.pop() {
+            region.plots.insert(pos);
+            for next_pos in pos.adjacent() {
+                if plots.get(&next_pos) == Some(&plant) && seen.insert(next_pos) {
+                    to_visit.push(next_pos);
+                }
+            }
+        }
+
+        regions.push(region);
+    }
+
+    regions
+}
+
+// The index of the smallest region with a hole around each region, if there is one.
+pub fn find_enclosing(regions: &[Region]) -> Vec<Option<usize>> {
+    let holes: Vec<Vec<Polygon>> = regions
+        .iter()
+        .map(|region| {
+            region
+                .boundaries()
+                .into_iter()
+                .filter(Polygon::is_hole)
+                .collect()
+        })
+        .collect();
+
+    regions
+        .iter()
+        .map(|region| {
+            let plot = *region.plots.iter().next().unwrap();
+            holes
+                .iter()
+                .enumerate()
+                .filter(|(_, holes)| holes.iter().any(|hole| hole.contains(plot)))
+                .map(|(index, _)| index)
+                .min_by_key(|&index| regions[index].area())
+        })
+        .collect()
+}
+
+// Outlines every region, filled with a colour for its plant and titled with its details.
+pub fn to_svg(regions: &[Region]) -> String {
+    let width = regions
+        .iter()
+        .map(|region| region.bounding_box().max.x + 1)
+        .max()
+        .unwrap_or(0)
+        * SCALE;
+    let height = regions
+        .iter()
+        .map(|region| region.bounding_box().max.y + 1)
+        .max()
+        .unwrap_or(0)
+        * SCALE;
+
+    let mut svg = String::new();
+    writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="0 0 {} {}">"#,
+        width, height, width, height
+    )
+    .unwrap();
+
+    for ((index, region), enclosing) in regions.iter().enumerate().zip(find_enclosing(regions)) {
+        let bounding_box = region.bounding_box();
+        let mut title = format!(
+            "Region {}: {}, area {}, {} sides, {} holes, {} to {}",
+            index,
+            region.plant,
+            region.area(),
+            region.sides(),
+            region.holes(),
+            bounding_box.min,
+            bounding_box.max
+        );
+        if let Some(enclosing) = enclosing {
+            write!(title, ", inside region {}", enclosing).unwrap();
+        }
+
+        writeln!(
+            svg,
+            r#"<path d="{}" fill="hsl({}, 60%, 55%)" fill-rule="evenodd" stroke="black"><title>{}</title></path>"#,
+            region.boundaries().iter().map(Polygon::svg_path).join(" "),
+            region.plant as u32 * 47 % 360,
+            escape_xml(&title)
+        )
+        .unwrap();
+    }
+
+    writeln!(svg, "</svg>").unwrap();
+    svg
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::{find_enclosing, find_regions, to_svg, BoundingBox, Polygon};
+    use crate::common::Position;
+
+    fn grid(data: &str) -> HashMap<Position, char> {
+        data.lines()
+            .enumerate()
+            .flat_map(|(y, row)| row.char_indices().map(move |(x, c)| ((x, y).into(), c)))
+            .collect()
+    }
+
+    fn polygon(corners: &[(i64, i64)]) -> Polygon {
+        Polygon::new(corners.iter().copied().map(Position::from).collect())
+    }
+
+    #[test]
+    fn test_boundaries() {
+        let regions = find_regions(&grid("AAA\nABA\nAAA"));
+        assert_eq!(regions.len(), 2);
+
+        let outer = &regions[0];
+        assert_eq!(outer.holes(), 1);
+        assert_eq!(outer.sides(), 8);
+        assert_eq!(
+            outer.boundaries(),
+            vec![
+                polygon(&[(0, 0), (3, 0), (3, 3), (0, 3)]),
+                polygon(&[(1, 1), (1, 2), (2, 2), (2, 1)]),
+            ]
+        );
+        assert_eq!(
+            regions[1].bounding_box(),
+            BoundingBox {
+                min: Position { x: 1, y: 1 },
+                max: Position { x: 1, y: 1 }
+            }
+        );
+
+        // The gap only touches the outside at a corner, so it isn't a hole.
+        let regions = find_regions(&grid("AA.\nA.A\nAAA"));
+        assert_eq!(regions[0].holes(), 0);
+        assert_eq!(regions[0].sides(), 10);
+    }
+
+    #[test]
+    fn test_nesting() {
+        let regions = find_regions(&grid("AAAAA\nABBBA\nABCBA\nABBBA\nAAAAA"));
+        assert_eq!(find_enclosing(&regions), vec![None, Some(0), Some(1)]);
+
+        let svg = to_svg(&regions);
+        assert!(svg.contains(r#"width="40" height="40""#));
+        assert_eq!(svg.matches("<path").count(), 3);
+        assert!(svg
+            .contains("Region 2: C, area 1, 4 sides, 0 holes, (2, 2) to (2, 2), inside region 1"));
+
+        let svg = to_svg(&find_regions(&grid("<&")));
+        assert!(svg.contains("Region 0: &lt;,"));
+        assert!(svg.contains("Region 1: &amp;,"));
+    }
+}